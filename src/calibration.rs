@@ -0,0 +1,331 @@
+use crate::error::{Error, Result};
+use crate::types::*;
+use crate::Journey;
+use serde::Serialize;
+
+/// the smallest predicted share, so that the log of it is finite
+pub(crate) const MIN_SHARE: f64 = 1e-9;
+
+/// the largest concentration to search, because the beta distribution
+/// becomes numerically unstable beyond it
+const MAX_CONCENTRATION: f64 = 1000.0;
+
+/// How well the predicted car shares match the observed ones
+#[derive(Clone, Debug, Serialize)]
+pub struct FitReport {
+    /// the average over the stations of -Σ observed × ln(predicted), which is
+    /// the negative log likelihood per passenger
+    pub cross_entropy: f64,
+    pub rmse: f64,
+    pub max_abs_error: f64,
+}
+
+/// The stair model fitted to the observed car shares
+#[derive(Clone, Debug, Serialize)]
+pub struct Calibration {
+    pub stair_model: StairModel,
+    /// the fit of the stair model of the journey, before calibration
+    pub initial_fit: FitReport,
+    pub fit: FitReport,
+    pub iterations: usize,
+}
+
+/// The predicted share of every observed car, in the same order. The shares
+/// are normalised over the observed cars of each station, like the observed
+/// shares, so that stations observed in only some cars can be compared
+pub fn predicted_shares(
+    journey: &Journey,
+    formation: &Formation,
+    observed: &[ObservedCar],
+) -> Result<Vec<f64>> {
+    let mut car_loads: Vec<Option<Vec<CarLoad>>> =
+        vec![None; journey.stations().len()];
+    let shares: Vec<f64> = observed
+        .iter()
+        .map(|row| {
+            let idx = journey.station_index(&row.station).ok_or_else(|| {
+                Error::BadObservation {
+                    message: format!("{} is not in the journey", row.station),
+                }
+            })?;
+            if !(1..=formation.cars).contains(&row.car) {
+                return Err(Error::BadObservation {
+                    message: format!(
+                        "car {} of {} is not in the {} car formation",
+                        row.car, row.station, formation.cars
+                    ),
+                });
+            }
+            let loads = car_loads[idx]
+                .get_or_insert_with(|| journey.car_loads(idx, formation));
+            Ok(loads[row.car - 1].share)
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(observed
+        .iter()
+        .zip(&shares)
+        .map(|(row, share)| {
+            let total: f64 = observed
+                .iter()
+                .zip(&shares)
+                .filter(|(other, _)| other.station == row.station)
+                .map(|(_, share)| share)
+                .sum();
+            share / total.max(MIN_SHARE)
+        })
+        .collect())
+}
+
+/// compares the predicted shares with the observed ones
+pub fn fit_report(observed: &[ObservedCar], predicted: &[f64]) -> FitReport {
+    let mut stations: Vec<&str> = vec![];
+    for row in observed {
+        if !stations.contains(&row.station.as_str()) {
+            stations.push(&row.station);
+        }
+    }
+    let cross_entropy = observed
+        .iter()
+        .zip(predicted)
+        .map(|(row, p)| -row.share * p.max(MIN_SHARE).ln())
+        .sum::<f64>()
+        / stations.len().max(1) as f64;
+
+    let errors: Vec<f64> = observed
+        .iter()
+        .zip(predicted)
+        .map(|(row, p)| p - row.share)
+        .collect();
+    let n = errors.len().max(1) as f64;
+    FitReport {
+        cross_entropy,
+        rmse: (errors.iter().map(|e| e * e).sum::<f64>() / n).sqrt(),
+        max_abs_error: errors.iter().map(|e| e.abs()).fold(0.0, f64::max),
+    }
+}
+
+/// the unconstrained parameters searched by Nelder-Mead. The proportions are
+/// the softmax of (t_f, t_c, 0) so that they are positive and sum to 1, and
+/// the concentrations are 2 + exp(t) so that they are at least 2
+fn encode(stair_model: &StairModel) -> Vec<f64> {
+    let uniform = stair_model.prop_uniform.max(MIN_SHARE);
+    vec![
+        (stair_model.prop_normal_far.max(MIN_SHARE) / uniform).ln(),
+        (stair_model.prop_normal_close.max(MIN_SHARE) / uniform).ln(),
+        (stair_model.far_concentration - 2.0).max(MIN_SHARE).ln(),
+        (stair_model.close_concentration - 2.0).max(MIN_SHARE).ln(),
+    ]
+}
+
+fn decode(params: &[f64], base: &StairModel) -> StairModel {
+    let weights = [params[0].exp(), params[1].exp(), 1.0];
+    let total: f64 = weights.iter().sum();
+    let concentration = |t: f64| (2.0 + t.exp()).min(MAX_CONCENTRATION);
+    StairModel {
+        prop_normal_far: weights[0] / total,
+        prop_normal_close: weights[1] / total,
+        prop_uniform: weights[2] / total,
+        far_concentration: concentration(params[2]),
+        close_concentration: concentration(params[3]),
+        ..base.clone()
+    }
+}
+
+/// Fits the proportions and concentrations of the stair model of the journey
+/// to the observed car shares, by minimising the cross entropy with
+/// Nelder-Mead. The alighting attraction and destination blend are kept
+pub fn calibrate(
+    journey: &Journey,
+    formation: &Formation,
+    observed: &[ObservedCar],
+    max_iterations: usize,
+) -> Result<Calibration> {
+    let initial_fit =
+        fit_report(observed, &predicted_shares(journey, formation, observed)?);
+
+    let base = journey.stair_model().clone();
+    let objective = |params: &[f64]| {
+        // the observations were already checked against the journey, and
+        // decode always gives a valid stair model
+        journey
+            .clone()
+            .with_stair_model(decode(params, &base))
+            .and_then(|fitted| predicted_shares(&fitted, formation, observed))
+            .map(|predicted| fit_report(observed, &predicted).cross_entropy)
+            .unwrap_or(f64::INFINITY)
+    };
+    let (params, iterations) =
+        nelder_mead(objective, encode(&base), max_iterations);
+
+    let stair_model = decode(&params, &base);
+    let fitted = journey.clone().with_stair_model(stair_model.clone())?;
+    let fit =
+        fit_report(observed, &predicted_shares(&fitted, formation, observed)?);
+    Ok(Calibration {
+        stair_model,
+        initial_fit,
+        fit,
+        iterations,
+    })
+}
+
+/// minimises f from x0 with the Nelder-Mead simplex method, returning the
+/// best point and the number of iterations
+fn nelder_mead(
+    f: impl Fn(&[f64]) -> f64,
+    x0: Vec<f64>,
+    max_iterations: usize,
+) -> (Vec<f64>, usize) {
+    const TOLERANCE: f64 = 1e-10;
+    let n = x0.len();
+    let mut simplex: Vec<(Vec<f64>, f64)> = vec![(x0.clone(), f(&x0))];
+    for i in 0..n {
+        let mut x = x0.clone();
+        x[i] += 0.5;
+        let fx = f(&x);
+        simplex.push((x, fx));
+    }
+
+    // moves from the centroid c towards or away from x by the coefficient
+    let towards = |c: &[f64], x: &[f64], coefficient: f64| -> Vec<f64> {
+        c.iter()
+            .zip(x)
+            .map(|(c, x)| c + coefficient * (x - c))
+            .collect()
+    };
+
+    for iteration in 0..max_iterations {
+        simplex.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        let best = simplex[0].1;
+        let worst = simplex[n].1;
+        if (worst - best).abs() < TOLERANCE {
+            return (simplex.swap_remove(0).0, iteration);
+        }
+
+        let centroid: Vec<f64> = (0..n)
+            .map(|i| {
+                simplex[..n].iter().map(|(x, _)| x[i]).sum::<f64>() / n as f64
+            })
+            .collect();
+        let reflected = towards(&centroid, &simplex[n].0, -1.0);
+        let f_reflected = f(&reflected);
+
+        if f_reflected < best {
+            let expanded = towards(&centroid, &simplex[n].0, -2.0);
+            let f_expanded = f(&expanded);
+            simplex[n] = if f_expanded < f_reflected {
+                (expanded, f_expanded)
+            } else {
+                (reflected, f_reflected)
+            };
+        } else if f_reflected < simplex[n - 1].1 {
+            simplex[n] = (reflected, f_reflected);
+        } else {
+            let contracted = if f_reflected < worst {
+                towards(&centroid, &reflected, 0.5)
+            } else {
+                towards(&centroid, &simplex[n].0, 0.5)
+            };
+            let f_contracted = f(&contracted);
+            if f_contracted < worst.min(f_reflected) {
+                simplex[n] = (contracted, f_contracted);
+            } else {
+                // shrink towards the best point
+                let best_x = simplex[0].0.clone();
+                for (x, fx) in simplex.iter_mut().skip(1) {
+                    *x = towards(&best_x, x, 0.5);
+                    *fx = f(x);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    (simplex.swap_remove(0).0, max_iterations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journey::test_journey;
+
+    /// the shares of the first 4 cars of some stations, as predicted by a
+    /// journey and normalised over those cars
+    fn partial_observations(journey: &Journey) -> Vec<ObservedCar> {
+        let formation = Formation::default();
+        let mut observed = vec![];
+        for station in ["神田", "四ッ谷"] {
+            let idx = journey.station_index(station).unwrap();
+            let loads = journey.car_loads(idx, &formation);
+            let total: f64 = loads[..4].iter().map(|load| load.share).sum();
+            observed.extend(loads[..4].iter().enumerate().map(
+                |(car, load)| ObservedCar {
+                    station: station.to_string(),
+                    car: car + 1,
+                    share: load.share / total,
+                },
+            ));
+        }
+        observed
+    }
+
+    #[test]
+    fn predicted_shares_are_normalised_over_the_observed_cars() {
+        let journey = test_journey(StairModel::default());
+        let observed = partial_observations(&journey);
+        let predicted =
+            predicted_shares(&journey, &Formation::default(), &observed)
+                .unwrap();
+        for station in ["神田", "四ッ谷"] {
+            let total: f64 = observed
+                .iter()
+                .zip(&predicted)
+                .filter(|(row, _)| row.station == station)
+                .map(|(_, p)| p)
+                .sum();
+            assert!(
+                (total - 1.0).abs() < 1e-9,
+                "{} sums to {}",
+                station,
+                total
+            );
+        }
+        let fit = fit_report(&observed, &predicted);
+        assert!(fit.max_abs_error < 1e-9);
+    }
+
+    #[test]
+    fn calibrates_to_some_of_the_cars() {
+        let target = StairModel {
+            prop_normal_far: 0.6,
+            prop_normal_close: 0.2,
+            prop_uniform: 0.2,
+            far_concentration: 30.0,
+            ..StairModel::default()
+        };
+        let observed = partial_observations(&test_journey(target));
+        let calibration = calibrate(
+            &test_journey(StairModel::default()),
+            &Formation::default(),
+            &observed,
+            500,
+        )
+        .unwrap();
+        assert!(
+            calibration.fit.cross_entropy
+                <= calibration.initial_fit.cross_entropy
+        );
+        assert!(calibration.fit.rmse < 0.01, "{:?}", calibration.fit);
+    }
+
+    #[test]
+    fn nelder_mead_minimises_a_quadratic() {
+        let f = |x: &[f64]| {
+            (x[0] - 1.0).powi(2) + 10.0 * (x[1] + 2.0).powi(2) + 3.0
+        };
+        let (x, iterations) = nelder_mead(f, vec![0.0, 0.0], 1000);
+        assert!(iterations < 1000);
+        assert!((x[0] - 1.0).abs() < 1e-3, "x = {:?}", x);
+        assert!((x[1] + 2.0).abs() < 1e-3, "x = {:?}", x);
+    }
+}
@@ -1,146 +1,644 @@
 use crate::data::read_data::*;
 use crate::data::utils::*;
+use crate::error::{Error, Result};
 use crate::types::*;
+use csv::StringRecord;
+use rand::distributions::Distribution;
+use rand::Rng;
 use statrs::distribution::Continuous;
 use statrs::distribution::Uniform;
 
-pub fn make_pdfs_for_all_stations(
-    all_station_stairs: &[StationStairs],
-    boarder_props: &[f64],
-) -> Vec<Vec<(f64, f64)>> {
-    all_station_stairs
+/// the positions the pdfs are sampled at, as a proportion of the platform
+fn sample_xs(grid: &SampleGrid) -> Vec<f64> {
+    grid.xs().iter().map(|x| x / 100.0).collect()
+}
+
+/// the components of S for every stair of a station, weighted by its traffic
+fn make_stair_components(
+    station: &StationStairs,
+    stair_model: &StairModel,
+    xs: &[f64],
+) -> Vec<Vec<StairComponents>> {
+    station
+        .stair_locations
         .iter()
-        .enumerate()
-        .map(|(idx, _)| {
-            (1..=100)
+        .zip(&station.stair_weights)
+        .map(|(location, w)| {
+            xs.iter()
                 .map(|x| {
-                    let y = make_pdf_for_station(
-                        all_station_stairs,
-                        boarder_props,
-                        idx,
-                        x as f64 / 100.0,
-                    );
-                    (x as f64, y)
+                    let (a, b, c) = stair_pdfs_sep(location, *x, stair_model);
+                    (a * w, b * w, c * w)
                 })
                 .collect()
         })
         .collect()
 }
 
-/// m
-// TODO: if it's too slow then memoize it (f64 cannot be hashed but can work around
-// that using loops)
-//#[cached(
-//    type = "UnboundCache<(usize, i32), f64>",
-//    create = "{ UnboundCache::new() }",
-//    convert = " { (i, x) } "
-//)]
-fn make_pdf_for_station(
-    stations: &[StationStairs],
-    boarders_props: &[f64],
-    i: usize,
-    x: f64,
-) -> f64 {
-    let boarder_pdf = make_boarding_pdf_for_station(stations, i, x);
-    if i == 0 {
-        boarder_pdf
-    } else {
-        let boarders_as_prop_of_new = boarders_props[i];
-        let remaining_pdf =
-            make_pdf_for_station(stations, boarders_props, i - 1, x);
-        let remaining_weighted_pdf =
-            remaining_pdf * (1.0 - boarders_as_prop_of_new);
-        let boarders_weighted_pdf = boarder_pdf * boarders_as_prop_of_new;
-        remaining_weighted_pdf + boarders_weighted_pdf
-    }
+/// E, the passengers close to the stairs of a station, from the components
+/// of its stairs
+fn make_stair_pdf(
+    stair_components: &[Vec<StairComponents>],
+    n: usize,
+) -> Vec<f64> {
+    (0..n)
+        .map(|x| {
+            stair_components
+                .iter()
+                .map(|stair| {
+                    let (a, b, c) = stair[x];
+                    a + b + c
+                })
+                .sum()
+        })
+        .collect()
 }
 
-/// b
-fn make_boarding_pdf_for_station(
-    stations: &[StationStairs],
-    i: usize,
-    x: f64,
-) -> f64 {
-    stations[i]
-        .stair_locations
+/// Computes the densities of every station in a single pass in the order of
+/// travel, so that m_{i-1} is only computed once for m_i. Returns m with the
+/// intermediate densities it was made from. If a dispersion is given, m is
+/// dispersed so that it is not above the capacity of every station
+pub fn make_densities_for_all_stations(
+    all_station_stairs: &[StationStairs],
+    boarder_props: &[f64],
+    alighter_props: &[f64],
+    destination_flows: &[Vec<f64>],
+    stair_model: &StairModel,
+    grid: &SampleGrid,
+    dispersion: Option<&Dispersion>,
+) -> Result<Vec<(Pdf, StationDensities)>> {
+    let xs = sample_xs(grid);
+    let with_xs =
+        |ys: Vec<f64>| Pdf(xs.iter().map(|x| x * 100.0).zip(ys).collect());
+
+    let stair_components: Vec<_> = all_station_stairs
         .iter()
-        .map(|stair| {
-            stair_pdfs(stair, x) / stations[i].stair_locations.len() as f64
-        })
-        .sum()
+        .map(|station| make_stair_components(station, stair_model, &xs))
+        .collect();
+    let stair_pdfs: Vec<_> = stair_components
+        .iter()
+        .map(|components| make_stair_pdf(components, xs.len()))
+        .collect();
+
+    let mut prev_pdf: Option<Vec<f64>> = None;
+    let mut densities = Vec::with_capacity(all_station_stairs.len());
+    for (i, components) in stair_components.into_iter().enumerate() {
+        let boarding_pdf = make_boarding_pdf_for_station(
+            &stair_pdfs,
+            &destination_flows[i],
+            stair_model,
+            i,
+        );
+        // nobody is in the train before the first station, or after a station
+        // where everyone alights, so the train starts again with the boarders
+        if alighter_props[i] >= 1.0 {
+            prev_pdf = None;
+        }
+        let remaining_pdf = match &prev_pdf {
+            Some(prev_pdf) => make_remaining_pdf(
+                prev_pdf,
+                &stair_pdfs[i],
+                alighter_props[i],
+                stair_model,
+                &xs,
+            ),
+            None => vec![0.0; xs.len()],
+        };
+        let mixture_pdf: Vec<f64> = if prev_pdf.is_some() {
+            remaining_pdf
+                .iter()
+                .zip(&boarding_pdf)
+                .map(|(remaining, boarder)| {
+                    remaining * (1.0 - boarder_props[i])
+                        + boarder * boarder_props[i]
+                })
+                .collect()
+        } else {
+            boarding_pdf.clone()
+        };
+        let pdf = match dispersion {
+            Some(dispersion) => {
+                let (front, back) = dispersion.train;
+                let train = (front / 100.0, back / 100.0);
+                disperse(&mixture_pdf, dispersion.capacities[i], train, &xs)
+                    .map_err(|message| Error::Dispersion {
+                        station: all_station_stairs[i].station_name.clone(),
+                        message,
+                    })?
+            }
+            None => mixture_pdf.clone(),
+        };
+
+        let stair_components = components
+            .into_iter()
+            .map(|stair| xs.iter().map(|x| x * 100.0).zip(stair).collect())
+            .collect();
+        densities.push((
+            with_xs(pdf.clone()),
+            StationDensities {
+                boarding_pdf: with_xs(boarding_pdf),
+                remaining_pdf: with_xs(remaining_pdf),
+                stair_pdf: with_xs(stair_pdfs[i].clone()),
+                stair_components,
+                mixture_pdf: with_xs(mixture_pdf),
+            },
+        ));
+        prev_pdf = Some(pdf);
+    }
+    Ok(densities)
 }
 
-/// S
-fn stair_pdfs(stair: &f64, x: f64) -> f64 {
-    let (a, b, c) = stair_pdfs_sep(stair, x);
-    a + b + c
+/// the most sweeps over the platform to disperse a pdf
+const MAX_DISPERSION_SWEEPS: usize = 100_000;
+
+/// Spreads the density above the capacity to the neighbouring positions in
+/// the train, as passengers move away from a full part of it, until no
+/// position is above it. The train and xs are proportions of the platform.
+/// The passengers outside the train or the sampled positions are spread over
+/// its cars, as in the car loads, so the capacity inside the train is lowered
+/// to the share of the passengers that are inside it. Fails if the passengers
+/// do not fit in the sampled part of the train, or if they do not settle
+fn disperse(
+    ys: &[f64],
+    capacity: f64,
+    train: (f64, f64),
+    xs: &[f64],
+) -> std::result::Result<Vec<f64>, String> {
+    let mass = integrate(ys, xs);
+    let inside: Vec<usize> = (0..xs.len())
+        .filter(|k| (train.0..=train.1).contains(&xs[*k]))
+        .collect();
+    if mass <= 0.0 {
+        return Ok(ys.to_vec());
+    }
+
+    // the trapezoid weights of the samples, so that moving density between
+    // samples conserves the integral exactly
+    let weights: Vec<f64> = (0..xs.len())
+        .map(|k| {
+            let before = if k > 0 { xs[k] - xs[k - 1] } else { 0.0 };
+            let after = xs.get(k + 1).map_or(0.0, |next| next - xs[k]);
+            (before + after) / 2.0
+        })
+        .collect();
+    let width: f64 = inside.iter().map(|k| weights[*k]).sum();
+    // every passenger is in the train, so the pdf of the passengers in the
+    // train has a mass of 1 even if some of it is outside the sampled part
+    if capacity * width <= 1.0 {
+        return Err(format!(
+            "the sampled {:.0}% of the platform in the train only fits {:.0}% of the passengers",
+            width * 100.0,
+            capacity * width * 100.0
+        ));
+    }
+    let inside_mass: f64 = inside.iter().map(|k| weights[*k] * ys[*k]).sum();
+    let capacity = capacity * inside_mass;
+
+    let mut ys = ys.to_vec();
+    for _ in 0..MAX_DISPERSION_SWEEPS {
+        let mut moved = false;
+        for (idx, k) in inside.iter().copied().enumerate() {
+            let excess = ys[k] - capacity;
+            if excess <= 1e-9 {
+                continue;
+            }
+            ys[k] = capacity;
+            moved = true;
+            let neighbours: Vec<usize> = [
+                idx.checked_sub(1),
+                Some(idx + 1).filter(|j| *j < inside.len()),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|j| inside[j])
+            .collect();
+            if neighbours.is_empty() {
+                ys[k] += excess;
+            }
+            for j in &neighbours {
+                ys[*j] +=
+                    excess * weights[k] / weights[*j] / neighbours.len() as f64;
+            }
+        }
+        if !moved {
+            return Ok(ys);
+        }
+    }
+    Err(format!(
+        "the passengers did not settle after {} sweeps",
+        MAX_DISPERSION_SWEEPS
+    ))
 }
 
-pub fn stair_pdfs_sep(stair: &f64, x: f64) -> (f64, f64, f64) {
-    let prop_normal_far = 0.6;
-    let prop_uniform = 0.1;
-    let prop_normal_close = 0.3;
+/// r, the passengers that stayed in the train after some of them alighted.
+/// Alighters are drawn from the passengers in the train, preferring those
+/// close to the stairs of the station
+fn make_remaining_pdf(
+    prev_pdf: &[f64],
+    stair_pdf: &[f64],
+    alighters_prop: f64,
+    stair_model: &StairModel,
+    xs: &[f64],
+) -> Vec<f64> {
+    let w = stair_model.alighting_attraction;
+    let attracted: Vec<f64> = prev_pdf
+        .iter()
+        .zip(stair_pdf)
+        .map(|(m, e)| m * ((1.0 - w) + w * e))
+        .collect();
+    let alighting_pdf = rescale_pdf(&attracted, 1.0, xs);
+
+    let remaining: Vec<f64> = prev_pdf
+        .iter()
+        .zip(alighting_pdf)
+        .map(|(m, a)| (m - a * alighters_prop).max(0.0))
+        .collect();
+    // keep the same mass as before alighting, so that if every position is
+    // equally likely to alight (w = 0) this is the same as just m_{i-1}
+    rescale_pdf(&remaining, integrate(prev_pdf, xs), xs)
+}
 
-    let far_concentration = 7.;
-    let close_concentration = 20.;
+/// b, a mixture of boarders waiting near their entry stairs and boarders
+/// waiting near the stairs they will exit from at their destination
+fn make_boarding_pdf_for_station(
+    stair_pdfs: &[Vec<f64>],
+    destination_flows: &[f64],
+    stair_model: &StairModel,
+    i: usize,
+) -> Vec<f64> {
+    // the last station has no destinations, so everyone is near their entry
+    let blend = if destination_flows.iter().sum::<f64>() > 0.0 {
+        stair_model.destination_blend
+    } else {
+        0.0
+    };
+    (0..stair_pdfs[i].len())
+        .map(|x| {
+            let exit: f64 = destination_flows
+                .iter()
+                .zip(stair_pdfs)
+                .map(|(flow, stair_pdf)| flow * stair_pdf[x])
+                .sum();
+            stair_pdfs[i][x] * (1.0 - blend) + exit * blend
+        })
+        .collect()
+}
 
+/// S, split into its far beta, close beta and uniform components
+pub fn stair_pdfs_sep(
+    stair: &f64,
+    x: f64,
+    stair_model: &StairModel,
+) -> StairComponents {
     let mean = clamp(*stair) / 100.0;
-    let a = beta(mean, far_concentration, x) * prop_normal_far;
-    let b = beta(mean, close_concentration, x) * prop_normal_close;
-    let c = Uniform::new(0.0, 1.).unwrap().pdf(x) * prop_uniform;
+    let a = beta(mean, stair_model.far_concentration, x)
+        * stair_model.prop_normal_far;
+    let b = beta(mean, stair_model.close_concentration, x)
+        * stair_model.prop_normal_close;
+    let c = Uniform::new(0.0, 1.).unwrap().pdf(x) * stair_model.prop_uniform;
 
     (a, b, c)
 }
 
-pub fn calc_proportion_of_boarders(stations: &[&str]) -> Vec<f64> {
-    let link_loads = read_link_load_data();
+/// samples a position from S, as a percentage of the platform
+pub fn sample_stair_position<R: Rng + ?Sized>(
+    stair: f64,
+    stair_model: &StairModel,
+    rng: &mut R,
+) -> f64 {
+    let mode = clamp(stair) / 100.0;
+    let u: f64 = rng.gen();
+    let x = if u < stair_model.prop_normal_far {
+        beta_distribution(mode, stair_model.far_concentration).sample(rng)
+    } else if u < stair_model.prop_normal_far + stair_model.prop_normal_close {
+        beta_distribution(mode, stair_model.close_concentration).sample(rng)
+    } else {
+        rng.gen()
+    };
+    x * 100.0
+}
+
+/// returns the name of the line as in the link load data, and the rows of the
+/// stations in the journey in the order of travel
+pub fn journey_rows(
+    dataset: &Dataset,
+    line: &str,
+    stations: &[&str],
+    direction: Direction,
+) -> Result<(String, Vec<StringRecord>)> {
+    let (line_name, rows) = find_line(&dataset.lines, line)?;
 
-    let line_loads: &Vec<_> = &link_loads
+    if let Some(station) = stations
         .iter()
-        .find(|(line, _)| line == "中央本線")
-        .unwrap()
-        .1
+        .find(|station| !rows.iter().any(|row| &&row[0] == *station))
+    {
+        return Err(Error::StationNotOnLine {
+            station: station.to_string(),
+            line: line_name.clone(),
+        });
+    }
+
+    let mut line_loads: Vec<_> = rows
         .iter()
         .filter(|record| stations.contains(&&record[0]))
+        .cloned()
         .collect();
 
-    // for the chuo line starting from tokyo, the direction is 'down'
-    // if it is up then columns 4 and 5 will be used instead of 1 and 2
-    let boardings = line_loads
+    // the rows are listed in the 'down' direction
+    if direction == Direction::Up {
+        line_loads.reverse();
+    }
+    Ok((line_name.clone(), line_loads))
+}
+
+/// returns the stations in the order of the rows of the journey
+pub fn order_stations<'a>(
+    rows: &[StringRecord],
+    stations: &[&'a str],
+) -> Vec<&'a str> {
+    rows.iter()
+        .filter_map(|row| stations.iter().find(|s| **s == &row[0]))
+        .copied()
+        .collect()
+}
+
+/// returns the passenger counts of every station in the rows of the journey
+pub fn parse_station_loads(
+    dataset: &Dataset,
+    line_name: &str,
+    rows: &[StringRecord],
+    direction: Direction,
+) -> Result<Vec<StationLoad>> {
+    let (boarding_col, alighting_col, passing_col) = direction.columns();
+
+    rows.iter()
+        .map(|row| {
+            let parse_count = |col: usize| {
+                row[col].replace(',', "").parse::<i64>().map_err(|_| {
+                    Error::BadLoadNumber {
+                        path: dataset.link_load_path.clone(),
+                        line: line_name.to_string(),
+                        station: row[0].to_string(),
+                        column: LINK_LOAD_COLUMNS[col],
+                        value: row[col].to_string(),
+                    }
+                })
+            };
+            let boardings = parse_count(boarding_col)?;
+            let alightings = parse_count(alighting_col)?;
+            let passing = parse_count(passing_col)?;
+            // the passing column counts the passengers between this station
+            // and the next one in the down direction, which is the previous
+            // station for the up direction
+            let onboard = match direction {
+                Direction::Down => passing,
+                Direction::Up => passing - alightings + boardings,
+            };
+            Ok(StationLoad {
+                boardings,
+                alightings,
+                onboard,
+            })
+        })
+        .collect()
+}
+
+pub fn calc_proportion_of_boarders(loads: &[StationLoad]) -> Vec<f64> {
+    // boarders as a percentage of total passengers in the train after the station
+    // first item is 1 for the first station of the line because 100% of
+    // passengers are boarders; none of them were passengers remaining from a
+    // "previous" station
+    // last item is 0 for the last station of the line because there are 0
+    // passengers after the last station
+    // "how many passengers out of a total of 0 passengers" == divide by zero
+    // replaced with 0 to be consistent
+    loads
         .iter()
-        .map(|row| row[1].replace(',', "").parse::<i64>().unwrap());
+        .map(|load| {
+            if load.onboard == 0 {
+                0.
+            } else {
+                load.boardings as f64 / load.onboard as f64
+            }
+        })
+        .collect()
+}
 
-    let alightings = line_loads
+/// for every station, the proportion of its boarders that alight at every
+/// station in the journey. Uses the origin-destination data if given,
+/// otherwise boarders are assumed to go to a downstream station in proportion
+/// to how many passengers alight there
+pub fn calc_destination_flows(
+    stations: &[&str],
+    loads: &[StationLoad],
+    od_rows: Option<&[OdRow]>,
+) -> Vec<Vec<f64>> {
+    let counts: Vec<Vec<f64>> = match od_rows {
+        Some(od_rows) => stations
+            .iter()
+            .map(|from| {
+                stations
+                    .iter()
+                    .map(|to| {
+                        od_rows
+                            .iter()
+                            .filter(|row| {
+                                row.from_station_code == *from
+                                    && row.to_station_code == *to
+                            })
+                            // summing no rows with sum gives -0.0
+                            .fold(0.0, |total, row| total + row.count as f64)
+                    })
+                    .collect()
+            })
+            .collect(),
+        None => stations
+            .iter()
+            .map(|_| loads.iter().map(|load| load.alightings as f64).collect())
+            .collect(),
+    };
+
+    // only downstream stations can be destinations
+    counts
         .iter()
-        .map(|row| row[2].replace(',', "").parse::<i64>().unwrap());
-
-    // manually calculating cumulative here, even though (part of) it is already
-    // in the third column, to prevent cumulative[0 - 1]
-    let difference = boardings.clone().zip(alightings).map(|(a, b)| a - b);
-    let mut cumulative = vec![];
-    for (idx, diff) in difference.enumerate() {
-        let x = if idx == 0 { 0 } else { cumulative[idx - 1] };
-        cumulative.push(diff + x)
-    }
+        .enumerate()
+        .map(|(from, row)| {
+            let downstream: Vec<f64> = row
+                .iter()
+                .enumerate()
+                .map(|(to, count)| if to > from { *count } else { 0.0 })
+                .collect();
+            if downstream.iter().sum::<f64>() > 0.0 {
+                normalize(downstream)
+            } else {
+                downstream
+            }
+        })
+        .collect()
+}
 
-    // boarders as a percentage of total passengers in the train after the station
-    let boarder_percs: Vec<_> = boardings
-        .zip(cumulative)
-        .map(|(boarding, cumulative)| {
-            if cumulative == 0 {
+/// alighters as a proportion of the passengers in the train before the station
+pub fn calc_proportion_of_alighters(loads: &[StationLoad]) -> Vec<f64> {
+    // the first station of the journey has no previous station to compare to,
+    // so nobody alights
+    loads
+        .iter()
+        .enumerate()
+        .map(|(idx, load)| {
+            let onboard_before = load.onboard_before();
+            if idx == 0 || onboard_before <= 0 {
                 0.
             } else {
-                boarding as f64 / cumulative as f64
+                load.alightings as f64 / onboard_before as f64
             }
         })
+        .collect()
+}
+
+/// the expected passengers in every car of every train, from the pdf after a
+/// station and the number of passengers in the trains after it. The shares
+/// are normalised over the cars, because passengers cannot be where the train
+/// is not
+pub fn calc_car_loads(
+    pdf: &Pdf,
+    formation: &Formation,
+    onboard: i64,
+) -> Vec<CarLoad> {
+    let masses: Vec<f64> = (0..formation.cars)
+        .map(|car| {
+            let (front, back) = formation.car_bounds(car);
+            pdf.mass_between(front, back)
+        })
         .collect();
+    let total: f64 = masses.iter().sum();
+    masses
+        .iter()
+        .enumerate()
+        .map(|(car, mass)| {
+            let share = if total > 0.0 { mass / total } else { 0.0 };
+            let count = share * onboard as f64 / formation.trains;
+            CarLoad {
+                car: car + 1,
+                share,
+                count,
+                load_factor: count / formation.capacity,
+            }
+        })
+        .collect()
+}
+
+/// the passengers per metre of the platform in every train, from the pdf
+/// after a station and the number of passengers in the trains after it
+pub fn calc_passengers_per_metre(
+    pdf: &Pdf,
+    formation: &Formation,
+    onboard: i64,
+) -> Pdf {
+    // the pdf is per proportion of the platform
+    let scale = onboard as f64 / formation.trains / formation.platform_length;
+    Pdf(pdf.iter().map(|(x, y)| (*x, y * scale)).collect())
+}
+
+/// the stairs of a station that are outside the platform, which are moved to
+/// its ends because the beta distribution is only defined inside it
+pub fn clamped_stairs(station: &StationStairs) -> Vec<f64> {
+    station
+        .stair_locations
+        .iter()
+        .filter(|stair| clamp(**stair) != **stair)
+        .copied()
+        .collect()
+}
 
-    // let stations = line_loads.iter().map(|row| &row[0]);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // first item is always 1 because 100% of passengers in the first station
-    // are boarders; none of them were passengers remaining from a "previous" station
-    // last item is always 0 because there are 0 passengers after the last station
-    // "how many passengers out of a total of 0 passengers" == divide by zero
-    // replaced with 0 to be consistent
-    boarder_percs
+    /// a sharp peak at the front of the platform, with a mass of 1
+    fn peak(xs: &[f64]) -> Vec<f64> {
+        let peak: Vec<f64> = xs.iter().map(|x| (-x * 10.0).exp()).collect();
+        rescale_pdf(&peak, 1.0, xs)
+    }
+
+    fn max(ys: &[f64]) -> f64 {
+        ys.iter().copied().fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn disperse_conserves_mass() {
+        let xs = sample_xs(&SampleGrid::default());
+        let ys = peak(&xs);
+        let capacity = 2.0;
+
+        let dispersed = disperse(&ys, capacity, (0.0, 1.0), &xs).unwrap();
+        assert!((integrate(&dispersed, &xs) - 1.0).abs() < 1e-9);
+        assert!(max(&dispersed) <= capacity + 1e-9);
+        assert!(max(&ys) > capacity);
+    }
+
+    #[test]
+    fn disperse_stays_inside_the_train() {
+        let xs = sample_xs(&SampleGrid::default());
+        let ys = peak(&xs);
+
+        let dispersed = disperse(&ys, 2.5, (0.0, 0.5), &xs).unwrap();
+        assert!((integrate(&dispersed, &xs) - 1.0).abs() < 1e-9);
+        for (k, x) in xs.iter().enumerate() {
+            if *x > 0.5 {
+                assert_eq!(dispersed[k], ys[k], "moved behind the train");
+            }
+        }
+    }
+
+    #[test]
+    fn disperse_rejects_passengers_that_do_not_fit() {
+        let xs = sample_xs(&SampleGrid::default());
+        let ys = peak(&xs);
+        // the train is full over half of the platform
+        assert!(disperse(&ys, 1.5, (0.0, 0.5), &xs).is_err());
+        // the grid only covers 60% of the train
+        let xs = sample_xs(&SampleGrid::new(20.0, 80.0, 61).unwrap());
+        let ys = rescale_pdf(&vec![1.0; xs.len()], 0.6, &xs);
+        assert!(disperse(&ys, 1.5, (0.0, 1.0), &xs).is_err());
+    }
+
+    #[test]
+    fn counts_passengers_in_the_up_direction() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let dataset = read_dataset(root).unwrap();
+        let stations = ["東京", "神田", "御茶ノ水", "四ッ谷"];
+        let (line, rows) =
+            journey_rows(&dataset, "中央本線", &stations, Direction::Up)
+                .unwrap();
+        let loads =
+            parse_station_loads(&dataset, &line, &rows, Direction::Up).unwrap();
+        let order = order_stations(&rows, &stations);
+        assert_eq!(order, ["四ッ谷", "御茶ノ水", "神田", "東京"]);
+        // the 上り 通過 column counts the passengers arriving at a station
+        assert_eq!(loads[1].onboard_before(), 299_051);
+        assert_eq!(loads[3].onboard, 0);
+        // 御茶ノ水, 神田 and 東京 are next to each other on the line
+        for pair in loads[1..].windows(2) {
+            assert_eq!(pair[0].onboard, pair[1].onboard_before());
+        }
+    }
+
+    #[test]
+    fn destination_flows_without_od_rows_are_positive_zero() {
+        let od_rows = [OdRow {
+            index: 0,
+            from_station_code: "東京".to_string(),
+            to_station_code: "神田".to_string(),
+            count: 10,
+            stations: vec![],
+        }];
+        let flows = calc_destination_flows(
+            &["東京", "神田", "御茶ノ水"],
+            &[],
+            Some(&od_rows),
+        );
+        assert_eq!(flows[0], [0.0, 1.0, 0.0]);
+        for flow in flows.iter().flatten() {
+            assert!(flow.is_sign_positive(), "{:?}", flows);
+        }
+    }
 }
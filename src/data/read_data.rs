@@ -1,32 +1,58 @@
 use crate::data::utils::*;
-use crate::types::StationStairs;
+use crate::error::{Error, Result};
+use crate::types::{
+    Dataset, Direction, Formation, ObservedCar, OdRow, StairModel,
+    StationStairs,
+};
 use csv::StringRecord;
 use std::cmp::Ordering;
-use std::iter;
 use std::ops::Deref;
+use std::path::Path;
 use svg::parser::Event;
 
+/// returns the standardized stair locations and their traffic weights. A stair
+/// guide with a numeric label uses it as the weight, otherwise the weight is 1.
+/// The weights must be finite and >= 0, and sum to more than 0
 pub fn read_stair_locations(
-    path: String,
-) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+    station: &str,
+    path: &str,
+) -> Result<(Vec<f64>, Vec<f64>)> {
     let mut content = String::new();
-    let events = svg::open(path, &mut content)?;
+    let events =
+        svg::open(path, &mut content).map_err(|source| Error::MissingMap {
+            station: station.to_string(),
+            path: path.to_string(),
+            source,
+        })?;
+    let bad_position = |position: &str| Error::BadGuidePosition {
+        station: station.to_string(),
+        path: path.to_string(),
+        position: position.to_string(),
+    };
+    let bad_weights = |message: String| Error::BadStairWeights {
+        station: station.to_string(),
+        path: path.to_string(),
+        message,
+    };
 
-    let mut guideline_pos: Vec<f64> = vec![];
+    let mut guidelines: Vec<(f64, f64)> = vec![];
     let mut start = None;
     let mut end = None;
     for event in events {
-        if let Event::Tag(path, _, attrs) = event {
-            if path == "sodipodi:guide" {
-                let raw_pos = attrs.get("position").unwrap();
+        if let Event::Tag(tag, _, attrs) = event {
+            if tag == "sodipodi:guide" {
+                let raw_pos =
+                    attrs.get("position").ok_or_else(|| bad_position(""))?;
                 let pos = raw_pos.deref();
                 // to_string() turn &str into String, which create
                 // a new allocation and own the data, because the &str pointer
                 // would be dropped at the end
-                let xpos_str = pos.split(',').next().unwrap().to_string();
-                let xpos: f64 = xpos_str.parse().unwrap();
+                let xpos_str = pos.split(',').next().unwrap_or("").to_string();
+                let xpos: f64 =
+                    xpos_str.trim().parse().map_err(|_| bad_position(pos))?;
 
                 let mut is_stair = true;
+                let mut weight = 1.0;
                 if let Some(raw_label) = attrs.get("inkscape:label") {
                     let label = raw_label.deref();
                     if label == "start" {
@@ -35,19 +61,32 @@ pub fn read_stair_locations(
                     } else if label == "end" {
                         end = Some(xpos);
                         is_stair = false;
+                    } else if let Ok(w) = label.trim().parse::<f64>() {
+                        if !w.is_finite() || w < 0.0 {
+                            return Err(bad_weights(format!(
+                                "the guide at {} has the weight \"{}\"",
+                                xpos, label
+                            )));
+                        }
+                        weight = w;
                     };
                 }
 
                 if !xpos.is_nan() && is_stair {
-                    guideline_pos.push(xpos);
+                    guidelines.push((xpos, weight));
                 };
             };
         };
     }
-    let start = start.unwrap();
-    let end = end.unwrap();
+    let missing_bound = |bound| Error::MissingPlatformBound {
+        station: station.to_string(),
+        path: path.to_string(),
+        bound,
+    };
+    let start = start.ok_or_else(|| missing_bound("start"))?;
+    let end = end.ok_or_else(|| missing_bound("end"))?;
 
-    guideline_pos.sort_by(|a, b| {
+    guidelines.sort_by(|(a, _), (b, _)| {
         if a < b {
             Ordering::Less
         } else if a > b {
@@ -56,74 +95,398 @@ pub fn read_stair_locations(
             Ordering::Equal
         }
     });
+    let (guideline_pos, weights): (Vec<f64>, Vec<f64>) =
+        guidelines.into_iter().unzip();
+    if weights.iter().sum::<f64>() <= 0.0 {
+        return Err(bad_weights(format!(
+            "the weights of the {} stairs sum to 0",
+            weights.len()
+        )));
+    }
 
     // to prevent stupid mistakes in labelling
     if start > end {
         // if start > end then start is max and end is min
-        Ok(standardize_between(start, end, guideline_pos))
+        Ok((standardize_between(start, end, guideline_pos), weights))
     } else {
         // if end > start then end is max and start is min
-        Ok(standardize_between(end, start, guideline_pos))
+        Ok((standardize_between(end, start, guideline_pos), weights))
     }
 }
 
-pub fn read_station_stairs(stations: Vec<&str>) -> Vec<StationStairs> {
+/// the stair locations are flipped for the up direction, so that the front of
+/// the train is always at 0
+pub fn read_station_stairs(
+    dataset: &Dataset,
+    stations: &[&str],
+    direction: Direction,
+) -> Result<Vec<StationStairs>> {
     stations
         .iter()
-        .map(|station| StationStairs {
-            station_name: station.to_string(),
-            stair_locations: read_stair_locations(format!(
-                "maps/{}.svg",
-                station
-            ))
-            .unwrap(),
+        .map(|station| {
+            let (mut stair_locations, mut weights) = read_stair_locations(
+                station,
+                &dataset.map_path(station).to_string_lossy(),
+            )?;
+            if direction == Direction::Up {
+                stair_locations =
+                    stair_locations.iter().rev().map(|x| 100.0 - x).collect();
+                weights.reverse();
+            }
+            Ok(StationStairs {
+                station_name: station.to_string(),
+                stair_locations,
+                stair_weights: normalize(weights),
+            })
         })
         .collect()
 }
 
-/// returns a mapping from lines (String) to stations and their data (Vec<StringRecord>)
-pub fn read_link_load_data() -> Vec<(String, Vec<StringRecord>)> {
-    let mut rdr = csv::Reader::from_path("data/001178992.csv").unwrap();
-    let mut records = vec![];
-    for result in rdr.records() {
-        let record = result;
-        if let Ok(r) = record {
-            records.push(r);
+/// reads a JSON file of stair model parameters, and validates them
+pub fn read_stair_model(path: &str) -> Result<StairModel> {
+    let error = |message: String| Error::StairModel {
+        path: Some(path.to_string()),
+        message,
+    };
+    let file = std::fs::File::open(path).map_err(|e| error(e.to_string()))?;
+    let stair_model: StairModel =
+        serde_json::from_reader(file).map_err(|e| error(e.to_string()))?;
+    match stair_model.validate() {
+        Err(Error::StairModel { message, .. }) => Err(error(message)),
+        result => result.map(|_| stair_model),
+    }
+}
+
+/// reads a JSON file of the train formation, and validates it
+pub fn read_formation(path: &str) -> Result<Formation> {
+    let error = |message: String| Error::Formation {
+        path: Some(path.to_string()),
+        message,
+    };
+    let file = std::fs::File::open(path).map_err(|e| error(e.to_string()))?;
+    let formation: Formation =
+        serde_json::from_reader(file).map_err(|e| error(e.to_string()))?;
+    match formation.validate() {
+        Err(Error::Formation { message, .. }) => Err(error(message)),
+        result => result.map(|_| formation),
+    }
+}
+
+/// reads a CSV file of observed car occupancy, with the columns station, car,
+/// share. The shares of every station are normalised to sum to 1
+pub fn read_observed_cars(path: &str) -> Result<Vec<ObservedCar>> {
+    let error = |source| Error::ObservedData {
+        path: path.to_string(),
+        source,
+    };
+    let mut rdr = csv::Reader::from_path(path).map_err(error)?;
+    let mut rows: Vec<ObservedCar> = vec![];
+    for result in rdr.deserialize() {
+        rows.push(result.map_err(error)?);
+    }
+
+    let totals: Vec<f64> = rows
+        .iter()
+        .map(|row| {
+            rows.iter()
+                .filter(|other| other.station == row.station)
+                .map(|other| other.share)
+                .sum()
+        })
+        .collect();
+    for (row, total) in rows.iter_mut().zip(totals) {
+        if total > 0.0 {
+            row.share /= total;
         }
     }
+    Ok(rows)
+}
+
+/// reads a CSV file of origin-destination data, with the columns
+/// index, from_station_code, to_station_code, count
+pub fn read_od_data(path: &str) -> Result<Vec<OdRow>> {
+    let error = |source| Error::OdData {
+        path: path.to_string(),
+        source,
+    };
+    let mut rdr = csv::Reader::from_path(path).map_err(error)?;
+    let mut rows = vec![];
+    for result in rdr.deserialize() {
+        rows.push(result.map_err(error)?);
+    }
+    Ok(rows)
+}
 
-    // add an empty row in the front so that partitioning works
-    let first_line = StringRecord::from(
-        iter::repeat("").take(records[0].len()).collect::<Vec<_>>(),
-    );
-    let mut r = vec![first_line];
-    r.extend(records);
-    let records = r;
+/// the link load data, relative to the data root
+pub const LINK_LOAD_PATH: &str = "data/001178992.csv";
 
-    // partition line rows and station rows (and remove total rows)
-    let mut result = vec![];
-    for record in records {
-        // if record is empty vec then exclude this row anyway
-        let is_total_row =
-            record.get(0).map(|string| string == "合計").unwrap_or(true);
+/// the directory of the station maps, relative to the data root
+pub const MAPS_DIR: &str = "maps";
+
+/// reads the link load data and finds the maps in a data root, which is the
+/// root of this repository or a copy of its data and maps directories
+pub fn read_dataset(root: &Path) -> Result<Dataset> {
+    let link_load_path: String =
+        root.join(LINK_LOAD_PATH).to_string_lossy().into();
+    Ok(Dataset {
+        lines: read_link_load_data(&link_load_path)?,
+        link_load_path,
+        maps_dir: root.join(MAPS_DIR),
+    })
+}
+
+/// the header of the link load data
+pub const LINK_LOAD_COLUMNS: [&str; 7] = [
+    "駅名",
+    "下り 乗車",
+    "下り 降車",
+    "下り 通過",
+    "上り 乗車",
+    "上り 降車",
+    "上り 通過",
+];
+
+/// returns a mapping from lines (String) to stations and their data
+/// (Vec<StringRecord>). Every line is a block of rows separated by an empty
+/// row, starting with the name of the line and ending with a total row
+pub fn read_link_load_data(
+    path: &str,
+) -> Result<Vec<(String, Vec<StringRecord>)>> {
+    let csv_error = |source| Error::LinkLoadData {
+        path: path.to_string(),
+        source,
+    };
+    let format_error = |line: u64, message: String| Error::LinkLoadFormat {
+        path: path.to_string(),
+        line,
+        message,
+    };
+
+    let mut rdr = csv::Reader::from_path(path).map_err(csv_error)?;
+    let headers = rdr.headers().map_err(csv_error)?;
+    if headers.iter().ne(LINK_LOAD_COLUMNS) {
+        return Err(format_error(
+            1,
+            format!(
+                "expected the columns {}, got {}",
+                LINK_LOAD_COLUMNS.join(","),
+                headers.iter().collect::<Vec<_>>().join(",")
+            ),
+        ));
+    }
 
+    // partition line rows and station rows (and remove total rows)
+    let mut result: Vec<(String, Vec<StringRecord>)> = vec![];
+    let mut in_block = false;
+    for record in rdr.records() {
+        let record = record.map_err(csv_error)?;
+        let line = record.position().map_or(0, |position| position.line());
         if record.iter().all(|string| string.is_empty()) {
-            result.push(vec![]);
-        } else if !is_total_row {
-            let l = result.len() - 1;
-            let last_vec = &mut result[l];
-            last_vec.push(record);
+            if !in_block {
+                return Err(format_error(
+                    line,
+                    "the name of a line must follow an empty row".to_string(),
+                ));
+            }
+            in_block = false;
+        } else if !in_block {
+            if &record[0] == "合計" {
+                return Err(format_error(
+                    line,
+                    "a total row must follow the name of a line".to_string(),
+                ));
+            }
+            result.push((record[0].to_string(), vec![]));
+            in_block = true;
+        } else if &record[0] != "合計" {
+            if let Some((_, stations)) = result.last_mut() {
+                stations.push(record);
+            }
         }
     }
 
-    // turn results into (line, stations-in-this-line)
-    result
+    if result.is_empty() {
+        return Err(format_error(1, "there are no lines".to_string()));
+    }
+    Ok(result)
+}
+
+/// turns full-width letters, digits and symbols into ASCII and removes spaces,
+/// eg: 鶴見線（１） into 鶴見線(1)
+fn normalize_line_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => {
+                char::from_u32(c as u32 - 0xFEE0).unwrap_or(c)
+            }
+            _ => c,
+        })
+        .collect()
+}
+
+/// the parts of a line name without the "線" suffix or the section number,
+/// eg: 京浜東北・根岸線 into [京浜東北・根岸, 京浜東北, 根岸]
+fn line_name_parts(name: &str) -> Vec<String> {
+    let name = normalize_line_name(name);
+    let name = match name.find('(') {
+        Some(idx) => &name[..idx],
+        None => &name,
+    };
+    let core = name.strip_suffix('線').unwrap_or(name);
+    let mut parts = vec![core.to_string()];
+    if core.contains('・') {
+        parts.extend(core.split('・').map(|part| part.to_string()));
+    }
+    parts
+}
+
+/// finds the line in the link load data, allowing for variants of its name.
+/// Exact matches are preferred, then matches ignoring full-width characters,
+/// then matches ignoring the "線" suffix, section numbers and lines that
+/// were merged with a "・". Lines sharing a name are told apart by their
+/// position among them, eg: 新宿線#2 for the second 新宿線
+pub fn find_line<'a>(
+    link_loads: &'a [(String, Vec<StringRecord>)],
+    line: &str,
+) -> Result<&'a (String, Vec<StringRecord>)> {
+    let names = line_names(link_loads);
+    let lines: Vec<_> = names.iter().zip(link_loads).collect();
+    let ambiguous = |candidates: &[&(&String, _)]| Error::AmbiguousLine {
+        line: line.to_string(),
+        candidates: candidates
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect(),
+    };
+
+    if let Some((_, found)) = lines.iter().find(|(name, _)| *name == line) {
+        return Ok(found);
+    }
+
+    let normalized = normalize_line_name(line);
+    let matches: Vec<_> = lines
+        .iter()
+        .filter(|(name, (raw, _))| {
+            normalize_line_name(raw) == normalized
+                || normalize_line_name(name) == normalized
+        })
+        .collect();
+    match matches.as_slice() {
+        [(_, found)] => return Ok(found),
+        [] => {}
+        _ => return Err(ambiguous(&matches)),
+    }
+
+    let query = line_name_parts(line);
+    let candidates: Vec<_> = lines
+        .iter()
+        .filter(|(_, (raw, _))| {
+            line_name_parts(raw).iter().any(|part| query.contains(part))
+        })
+        .collect();
+    match candidates.as_slice() {
+        [(_, found)] => Ok(found),
+        [] => Err(Error::LineNotFound {
+            line: line.to_string(),
+            available: names.clone(),
+        }),
+        _ => Err(ambiguous(&candidates)),
+    }
+}
+
+/// the names of the lines, with a "#" and their position among the lines of
+/// the same name if there are several, eg: 新宿線#1 and 新宿線#2
+pub fn line_names(link_loads: &[(String, Vec<StringRecord>)]) -> Vec<String> {
+    link_loads
+        .iter()
+        .enumerate()
+        .map(|(idx, (line, _))| {
+            let same_name = |(other, _): &&(String, _)| other == line;
+            if link_loads.iter().filter(same_name).count() > 1 {
+                let position =
+                    link_loads[..=idx].iter().filter(same_name).count();
+                format!("{}#{}", line, position)
+            } else {
+                line.clone()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link_loads() -> Vec<(String, Vec<StringRecord>)> {
+        [
+            "中央本線",
+            "京浜東北・根岸線",
+            "鶴見線（１）",
+            "新宿線",
+            "新宿線",
+        ]
         .iter()
-        .map(|vec| {
-            (
-                vec[0].get(0).unwrap().to_string(),
-                vec.iter().skip(1).cloned().collect::<Vec<_>>(),
-            )
+        .enumerate()
+        .map(|(idx, line)| {
+            let station = format!("station {}", idx);
+            let row = StringRecord::from(vec![station.as_str(); 7]);
+            (line.to_string(), vec![row])
         })
         .collect()
+    }
+
+    fn first_station(found: &(String, Vec<StringRecord>)) -> &str {
+        &found.1[0][0]
+    }
+
+    #[test]
+    fn finds_exact_names() {
+        let link_loads = link_loads();
+        let found = find_line(&link_loads, "中央本線").unwrap();
+        assert_eq!(found.0, "中央本線");
+    }
+
+    #[test]
+    fn finds_variants_of_names() {
+        let link_loads = link_loads();
+        for (line, expected) in [
+            ("鶴見線(1)", "鶴見線（１）"),
+            ("中央本", "中央本線"),
+            ("京浜東北線", "京浜東北・根岸線"),
+            ("根岸", "京浜東北・根岸線"),
+        ] {
+            assert_eq!(find_line(&link_loads, line).unwrap().0, expected);
+        }
+    }
+
+    #[test]
+    fn numbers_lines_sharing_a_name() {
+        let link_loads = link_loads();
+        let found = find_line(&link_loads, "新宿線#2").unwrap();
+        assert_eq!(first_station(found), "station 4");
+        let found = find_line(&link_loads, "新宿線＃１").unwrap();
+        assert_eq!(first_station(found), "station 3");
+    }
+
+    #[test]
+    fn rejects_ambiguous_names() {
+        let link_loads = link_loads();
+        for line in ["新宿線", "新宿"] {
+            match find_line(&link_loads, line) {
+                Err(Error::AmbiguousLine { candidates, .. }) => {
+                    assert_eq!(candidates, ["新宿線#1", "新宿線#2"])
+                }
+                result => panic!("{} gave {:?}", line, result),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert!(matches!(
+            find_line(&link_loads(), "山手線"),
+            Err(Error::LineNotFound { .. })
+        ));
+    }
 }
@@ -3,17 +3,49 @@ use statrs::distribution::Continuous;
 /// Reformulation of the beta distribution in terms of mode and concentration
 /// Larger concentration means more focused and lower variance
 pub fn beta(mode: f64, concentration: f64, x: f64) -> f64 {
+    beta_distribution(mode, concentration).pdf(x)
+}
+
+/// the beta distribution with the given mode and concentration
+pub fn beta_distribution(
+    mode: f64,
+    concentration: f64,
+) -> statrs::distribution::Beta {
     assert!((0.0..1.0).contains(&mode));
     assert!(concentration >= 2.0);
     let alpha = mode * (concentration - 2.0) + 1.0;
     let beta = concentration - alpha;
-    statrs::distribution::Beta::new(alpha, beta).unwrap().pdf(x)
+    statrs::distribution::Beta::new(alpha, beta).unwrap()
 }
 
 pub fn standardize_between(max: f64, min: f64, xs: Vec<f64>) -> Vec<f64> {
     xs.iter().map(|x| (x - min) / (max - min) * 100.0).collect()
 }
 
+/// scales the weights so that they sum to 1
+pub fn normalize(weights: Vec<f64>) -> Vec<f64> {
+    let sum: f64 = weights.iter().sum();
+    weights.iter().map(|w| w / sum).collect()
+}
+
+/// numerical integration of the sampled ys using the trapezoidal rule
+pub fn integrate(ys: &[f64], xs: &[f64]) -> f64 {
+    xs.windows(2)
+        .zip(ys.windows(2))
+        .map(|(x, y)| (x[1] - x[0]) * (y[0] + y[1]) / 2.0)
+        .sum()
+}
+
+/// scales the sampled pdf so that it integrates to the given mass
+pub fn rescale_pdf(ys: &[f64], mass: f64, xs: &[f64]) -> Vec<f64> {
+    let current = integrate(ys, xs);
+    if current == 0.0 {
+        ys.to_vec()
+    } else {
+        ys.iter().map(|y| y * mass / current).collect()
+    }
+}
+
 pub fn clamp(x: f64) -> f64 {
     if x > 0.0 && x < 100.0 {
         x
@@ -0,0 +1,83 @@
+use crate::data::clamped_stairs;
+use crate::types::*;
+use crate::Journey;
+use serde::Serialize;
+
+/// Summary statistics of a pdf, with positions as percentages of the platform
+#[derive(Clone, Debug, Serialize)]
+pub struct DensityStats {
+    /// the proportion of passengers on the platform, from 0 to 100, which
+    /// should be close to 1
+    pub mass: f64,
+    /// the position with the highest density
+    pub mode: f64,
+    pub mean: f64,
+    pub variance: f64,
+}
+
+impl DensityStats {
+    /// the mass lost (negative) or gained (positive) on the platform
+    pub fn mass_error(&self) -> f64 {
+        self.mass - 1.0
+    }
+}
+
+/// The diagnostics of m and b of a station
+#[derive(Clone, Debug, Serialize)]
+pub struct StationDiagnostics {
+    pub station: String,
+    /// m, the passengers in the train after the station
+    pub density: DensityStats,
+    /// b, the passengers boarding the train at the station
+    pub boarding_density: DensityStats,
+    /// stairs outside the platform, which were moved to its ends
+    pub clamped_stairs: Vec<f64>,
+}
+
+/// the mass over the platform, and the mode, mean and variance over every
+/// sampled position
+pub fn density_stats(pdf: &Pdf) -> DensityStats {
+    let (start, end) = match (pdf.first(), pdf.last()) {
+        (Some((start, _)), Some((end, _))) => (*start, *end),
+        _ => {
+            return DensityStats {
+                mass: 0.0,
+                mode: f64::NAN,
+                mean: f64::NAN,
+                variance: f64::NAN,
+            }
+        }
+    };
+    let moment = |f: &dyn Fn(f64) -> f64| {
+        Pdf(pdf.iter().map(|(x, y)| (*x, f(*x) * y)).collect())
+            .mass_between(start, end)
+    };
+    let total = pdf.mass_between(start, end);
+    let mean = moment(&|x| x) / total;
+    let variance = moment(&|x| (x - mean).powi(2)) / total;
+    let mode = pdf
+        .iter()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(f64::NAN, |(x, _)| *x);
+    DensityStats {
+        mass: pdf.mass_between(0.0, 100.0),
+        mode,
+        mean,
+        variance,
+    }
+}
+
+/// the diagnostics of every station in the journey
+pub fn diagnose(journey: &Journey) -> Vec<StationDiagnostics> {
+    journey
+        .stations()
+        .iter()
+        .enumerate()
+        .map(|(idx, station)| StationDiagnostics {
+            station: station.station_name.clone(),
+            density: density_stats(journey.density_after(idx)),
+            boarding_density: density_stats(journey.boarding_density(idx)),
+            clamped_stairs: clamped_stairs(station),
+        })
+        .collect()
+}
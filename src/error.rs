@@ -0,0 +1,263 @@
+use std::fmt;
+
+/// Errors from reading the input data of the model
+#[derive(Debug)]
+pub enum Error {
+    /// the map of a station could not be read
+    MissingMap {
+        station: String,
+        path: String,
+        source: std::io::Error,
+    },
+    /// the map has no guide labelled "start" or "end"
+    MissingPlatformBound {
+        station: String,
+        path: String,
+        bound: &'static str,
+    },
+    /// the position attribute of a guide in the map is missing or not a number
+    BadGuidePosition {
+        station: String,
+        path: String,
+        position: String,
+    },
+    /// a stair guide in the map has a negative or non-finite weight, or the
+    /// weights of the station do not sum to more than 0
+    BadStairWeights {
+        station: String,
+        path: String,
+        message: String,
+    },
+    /// the link load data could not be read
+    LinkLoadData {
+        path: String,
+        source: csv::Error,
+    },
+    /// the link load data does not have the expected columns, or a block of
+    /// rows does not start with the name of a line
+    LinkLoadFormat {
+        path: String,
+        line: u64,
+        message: String,
+    },
+    /// a cell in the link load data is not a number
+    BadLoadNumber {
+        path: String,
+        line: String,
+        station: String,
+        column: &'static str,
+        value: String,
+    },
+    LineNotFound {
+        line: String,
+        available: Vec<String>,
+    },
+    /// the line name matches more than one line
+    AmbiguousLine {
+        line: String,
+        candidates: Vec<String>,
+    },
+    StationNotOnLine {
+        station: String,
+        line: String,
+    },
+    /// the stair model config could not be read or is invalid
+    StairModel {
+        path: Option<String>,
+        message: String,
+    },
+    /// the domain is empty or there are fewer than 2 points
+    SampleGrid {
+        start: f64,
+        end: f64,
+        resolution: usize,
+    },
+    /// the origin-destination data could not be read
+    OdData {
+        path: String,
+        source: csv::Error,
+    },
+    /// the destination is not after the station of the journey
+    DestinationNotAfter {
+        station: String,
+        destination: String,
+    },
+    /// the observed car occupancy data could not be read
+    ObservedData {
+        path: String,
+        source: csv::Error,
+    },
+    /// the observed car occupancy data does not fit the journey
+    BadObservation {
+        message: String,
+    },
+    /// the passengers leaving a station do not fit in the trains, so they
+    /// cannot be dispersed
+    OverCapacity {
+        station: String,
+        onboard: i64,
+        capacity: f64,
+    },
+    /// the passengers after a station could not be dispersed along the
+    /// sampled part of the train
+    Dispersion {
+        station: String,
+        message: String,
+    },
+    /// the train formation config could not be read or is invalid
+    Formation {
+        path: Option<String>,
+        message: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingMap {
+                station,
+                path,
+                source,
+            } => write!(f, "cannot read the map of {} at {}: {}", station, path, source),
+            Error::MissingPlatformBound {
+                station,
+                path,
+                bound,
+            } => write!(
+                f,
+                "the map of {} at {} has no guide labelled \"{}\" for the platform bound",
+                station, path, bound
+            ),
+            Error::BadGuidePosition {
+                station,
+                path,
+                position,
+            } => write!(
+                f,
+                "the map of {} at {} has a guide with an invalid position \"{}\"",
+                station, path, position
+            ),
+            Error::BadStairWeights {
+                station,
+                path,
+                message,
+            } => write!(
+                f,
+                "the map of {} at {} has invalid stair weights: {}",
+                station, path, message
+            ),
+            Error::LinkLoadData { path, source } => {
+                write!(f, "cannot read the link load data at {}: {}", path, source)
+            }
+            Error::LinkLoadFormat {
+                path,
+                line,
+                message,
+            } => write!(
+                f,
+                "the link load data at {} is malformed at line {}: {}",
+                path, line, message
+            ),
+            Error::BadLoadNumber {
+                path,
+                line,
+                station,
+                column,
+                value,
+            } => write!(
+                f,
+                "the link load data at {} has \"{}\" for {} of {} on {}, which is not a number",
+                path, value, column, station, line
+            ),
+            Error::LineNotFound { line, available } => write!(
+                f,
+                "no line named {}. Available lines: {}",
+                line,
+                available.join(", ")
+            ),
+            Error::AmbiguousLine { line, candidates } => write!(
+                f,
+                "{} could be any of these lines: {}",
+                line,
+                candidates.join(", ")
+            ),
+            Error::StationNotOnLine { station, line } => {
+                write!(f, "{} is not a station on {}", station, line)
+            }
+            Error::StairModel {
+                path: Some(path),
+                message,
+            } => write!(f, "invalid stair model in {}: {}", path, message),
+            Error::StairModel {
+                path: None,
+                message,
+            } => write!(f, "invalid stair model: {}", message),
+            Error::SampleGrid {
+                start,
+                end,
+                resolution,
+            } => write!(
+                f,
+                "cannot sample {} points from {} to {}, the domain must not be empty and there must be at least 2 points",
+                resolution, start, end
+            ),
+            Error::OdData { path, source } => write!(
+                f,
+                "cannot read the origin-destination data at {}: {}",
+                path, source
+            ),
+            Error::DestinationNotAfter {
+                station,
+                destination,
+            } => write!(
+                f,
+                "the destination {} must come after {} in the journey",
+                destination, station
+            ),
+            Error::ObservedData { path, source } => write!(
+                f,
+                "cannot read the observed car occupancy data at {}: {}",
+                path, source
+            ),
+            Error::BadObservation { message } => {
+                write!(f, "invalid observed car occupancy: {}", message)
+            }
+            Error::OverCapacity {
+                station,
+                onboard,
+                capacity,
+            } => write!(
+                f,
+                "cannot disperse the passengers, {} of them leave {} but the trains only carry {}",
+                onboard, station, capacity
+            ),
+            Error::Dispersion { station, message } => write!(
+                f,
+                "cannot disperse the passengers after {}: {}",
+                station, message
+            ),
+            Error::Formation {
+                path: Some(path),
+                message,
+            } => write!(f, "invalid train formation in {}: {}", path, message),
+            Error::Formation {
+                path: None,
+                message,
+            } => write!(f, "invalid train formation: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::MissingMap { source, .. } => Some(source),
+            Error::LinkLoadData { source, .. } => Some(source),
+            Error::OdData { source, .. } => Some(source),
+            Error::ObservedData { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::calibration::{predicted_shares, MIN_SHARE};
+use crate::error::Result;
+use crate::types::*;
+use crate::Journey;
+use serde::Serialize;
+
+/// How well the predicted car shares of a station match the observed ones.
+/// The predicted shares are normalised over the observed cars, by
+/// `predicted_shares`
+#[derive(Clone, Debug, Serialize)]
+pub struct StationEvaluation {
+    pub station: String,
+    /// KL(observed || predicted), in nats
+    pub kl_divergence: f64,
+    /// the earth mover's distance between the observed and predicted
+    /// passengers along the train, in metres
+    pub wasserstein: f64,
+    pub rmse: f64,
+    pub max_abs_error: f64,
+    /// the predicted minus the observed share of every observed car, by car
+    pub residuals: Vec<(usize, f64)>,
+}
+
+/// Compares the predicted share of every observed car with the observed
+/// share, for every station with observations in the order of the journey
+pub fn evaluate(
+    journey: &Journey,
+    formation: &Formation,
+    observed: &[ObservedCar],
+) -> Result<Vec<StationEvaluation>> {
+    let predicted = predicted_shares(journey, formation, observed)?;
+
+    Ok(journey
+        .stations()
+        .iter()
+        .filter_map(|station| {
+            let mut cars: Vec<(usize, f64, f64)> = observed
+                .iter()
+                .zip(&predicted)
+                .filter(|(row, _)| row.station == station.station_name)
+                .map(|(row, p)| (row.car, row.share, *p))
+                .collect();
+            if cars.is_empty() {
+                return None;
+            }
+            cars.sort_by_key(|(car, _, _)| *car);
+            Some(evaluate_station(&station.station_name, &cars, formation))
+        })
+        .collect())
+}
+
+/// cars are (car, observed share, predicted share), sorted by car
+fn evaluate_station(
+    station: &str,
+    cars: &[(usize, f64, f64)],
+    formation: &Formation,
+) -> StationEvaluation {
+    let kl_divergence = cars
+        .iter()
+        .filter(|(_, o, _)| *o > 0.0)
+        .map(|(_, o, p)| o * (o / p.max(MIN_SHARE)).ln())
+        .sum();
+
+    // the area between the cdfs, with the passengers of a car at its centre
+    let mut cumulative_diff = 0.0;
+    let mut wasserstein = 0.0;
+    for pair in cars.windows(2) {
+        let (car, o, p) = pair[0];
+        cumulative_diff += o - p;
+        let distance = (pair[1].0 - car) as f64 * formation.car_length;
+        wasserstein += cumulative_diff.abs() * distance;
+    }
+
+    let residuals: Vec<(usize, f64)> =
+        cars.iter().map(|(car, o, p)| (*car, p - o)).collect();
+    let n = residuals.len() as f64;
+    StationEvaluation {
+        station: station.to_string(),
+        kl_divergence,
+        wasserstein,
+        rmse: (residuals.iter().map(|(_, e)| e * e).sum::<f64>() / n).sqrt(),
+        max_abs_error: residuals
+            .iter()
+            .map(|(_, e)| e.abs())
+            .fold(0.0, f64::max),
+        residuals,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate_cars(cars: &[(usize, f64, f64)]) -> StationEvaluation {
+        evaluate_station("東京", cars, &Formation::default())
+    }
+
+    #[test]
+    fn identical_shares_have_no_distance() {
+        let evaluation =
+            evaluate_cars(&[(1, 0.2, 0.2), (2, 0.5, 0.5), (4, 0.3, 0.3)]);
+        assert_eq!(evaluation.kl_divergence, 0.0);
+        assert_eq!(evaluation.wasserstein, 0.0);
+        assert_eq!(evaluation.rmse, 0.0);
+        assert_eq!(evaluation.max_abs_error, 0.0);
+    }
+
+    #[test]
+    fn wasserstein_is_the_distance_moved() {
+        let car_length = Formation::default().car_length;
+        let evaluation = evaluate_cars(&[(1, 1.0, 0.0), (2, 0.0, 1.0)]);
+        assert!((evaluation.wasserstein - car_length).abs() < 1e-9);
+        let evaluation = evaluate_cars(&[(1, 0.5, 0.0), (2, 0.5, 1.0)]);
+        assert!((evaluation.wasserstein - car_length / 2.0).abs() < 1e-9);
+        // the cars between observed cars still count towards the distance
+        let evaluation =
+            evaluate_cars(&[(1, 1.0, 0.0), (3, 0.0, 0.0), (5, 0.0, 1.0)]);
+        assert!((evaluation.wasserstein - 4.0 * car_length).abs() < 1e-9);
+    }
+
+    #[test]
+    fn kl_divergence_of_known_shares() {
+        let evaluation = evaluate_cars(&[(1, 0.5, 0.25), (2, 0.5, 0.75)]);
+        let expected = 0.5 * (2.0f64).ln() + 0.5 * (2.0f64 / 3.0).ln();
+        assert!((evaluation.kl_divergence - expected).abs() < 1e-12);
+        assert_eq!(evaluation.residuals, [(1, -0.25), (2, 0.25)]);
+        assert!((evaluation.max_abs_error - 0.25).abs() < 1e-12);
+    }
+}
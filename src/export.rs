@@ -0,0 +1,206 @@
+use crate::evaluation::StationEvaluation;
+use crate::sensitivity::SensitivityRun;
+use crate::types::*;
+use crate::Journey;
+use serde::Serialize;
+use std::fs::File;
+
+/// A row of the long-format CSV, one for every station and position
+#[derive(Serialize)]
+struct DensityRow<'a> {
+    station: &'a str,
+    x: f64,
+    density: f64,
+    boarder_density: f64,
+    boarder_prop: f64,
+}
+
+/// A row of the evaluation CSV, one for every station with observations
+#[derive(Serialize)]
+struct EvaluationRow<'a> {
+    station: &'a str,
+    kl_divergence: f64,
+    wasserstein: f64,
+    rmse: f64,
+    max_abs_error: f64,
+}
+
+/// The computed densities of a station, sampled at the same positions
+#[derive(Serialize)]
+struct Curves {
+    x: Vec<f64>,
+    /// m
+    density: Vec<f64>,
+    /// b
+    boarder_density: Vec<f64>,
+    /// r
+    remaining_density: Vec<f64>,
+    /// E
+    stair_density: Vec<f64>,
+}
+
+#[derive(Serialize)]
+struct StationDocument<'a> {
+    name: &'a str,
+    stair_locations: &'a [f64],
+    stair_weights: &'a [f64],
+    boarder_prop: f64,
+    alighter_prop: f64,
+    /// the proportion of boarders alighting at every station in the journey
+    destination_flows: &'a [f64],
+    curves: Curves,
+}
+
+#[derive(Serialize)]
+struct JourneyDocument<'a> {
+    line: &'a str,
+    direction: Direction,
+    stair_model: &'a StairModel,
+    grid: &'a SampleGrid,
+    stations: Vec<StationDocument<'a>>,
+}
+
+/// Writes the density and boarder density of every station in long format,
+/// with the columns station, x, density, boarder_density, boarder_prop
+pub fn write_densities_csv(
+    filename: &str,
+    journey: &Journey,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_path(filename)?;
+    for (idx, station) in journey.stations().iter().enumerate() {
+        let densities = journey.density_after(idx).iter();
+        let boarder_densities = journey.boarding_density(idx).densities();
+        for ((x, density), boarder_density) in densities.zip(boarder_densities)
+        {
+            writer.serialize(DensityRow {
+                station: &station.station_name,
+                x: *x,
+                density: *density,
+                boarder_density,
+                boarder_prop: journey.boarder_props()[idx],
+            })?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the journey, the stairs of every station, the parameters used and
+/// every computed curve as a JSON document
+pub fn write_journey_json(
+    filename: &str,
+    journey: &Journey,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stations = journey
+        .stations()
+        .iter()
+        .enumerate()
+        .map(|(idx, station)| StationDocument {
+            name: &station.station_name,
+            stair_locations: &station.stair_locations,
+            stair_weights: &station.stair_weights,
+            boarder_prop: journey.boarder_props()[idx],
+            alighter_prop: journey.alighter_props()[idx],
+            destination_flows: &journey.destination_flows()[idx],
+            curves: Curves {
+                x: journey.density_after(idx).xs().collect(),
+                density: journey.density_after(idx).densities().collect(),
+                boarder_density: journey
+                    .boarding_density(idx)
+                    .densities()
+                    .collect(),
+                remaining_density: journey
+                    .remaining_density(idx)
+                    .densities()
+                    .collect(),
+                stair_density: journey.stair_density(idx).densities().collect(),
+            },
+        })
+        .collect();
+
+    let document = JourneyDocument {
+        line: journey.line(),
+        direction: journey.direction(),
+        stair_model: journey.stair_model(),
+        grid: journey.grid(),
+        stations,
+    };
+    serde_json::to_writer_pretty(File::create(filename)?, &document)?;
+    Ok(())
+}
+
+/// Writes the metrics of every evaluated station, with the columns station,
+/// kl_divergence, wasserstein, rmse, max_abs_error
+pub fn write_evaluation_csv(
+    filename: &str,
+    evaluations: &[StationEvaluation],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_path(filename)?;
+    for evaluation in evaluations {
+        writer.serialize(EvaluationRow {
+            station: &evaluation.station,
+            kl_divergence: evaluation.kl_divergence,
+            wasserstein: evaluation.wasserstein,
+            rmse: evaluation.rmse,
+            max_abs_error: evaluation.max_abs_error,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes a row for every run and station, with the columns sample, varied
+/// (the parameter of a one-at-a-time sweep), the stair model parameters,
+/// station, front_share, back_share, peak_density and the share of every car
+/// from car_1
+pub fn write_sensitivity_csv(
+    filename: &str,
+    runs: &[SensitivityRun],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_path(filename)?;
+    let cars = runs
+        .first()
+        .and_then(|run| run.metrics.first())
+        .map_or(0, |metrics| metrics.car_shares.len());
+    let mut header: Vec<String> = [
+        "sample",
+        "varied",
+        "far_concentration",
+        "close_concentration",
+        "prop_normal_far",
+        "prop_normal_close",
+        "prop_uniform",
+        "station",
+        "front_share",
+        "back_share",
+        "peak_density",
+    ]
+    .iter()
+    .map(|column| column.to_string())
+    .collect();
+    header.extend((1..=cars).map(|car| format!("car_{}", car)));
+    writer.write_record(&header)?;
+
+    for (sample, run) in runs.iter().enumerate() {
+        let model = &run.stair_model;
+        for metrics in &run.metrics {
+            let mut record = vec![
+                sample.to_string(),
+                run.varied.map_or("", |p| p.name()).to_string(),
+                model.far_concentration.to_string(),
+                model.close_concentration.to_string(),
+                model.prop_normal_far.to_string(),
+                model.prop_normal_close.to_string(),
+                model.prop_uniform.to_string(),
+                metrics.station.clone(),
+                metrics.front_share.to_string(),
+                metrics.back_share.to_string(),
+                metrics.peak_density.to_string(),
+            ];
+            record.extend(metrics.car_shares.iter().map(|s| s.to_string()));
+            writer.write_record(&record)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
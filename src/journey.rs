@@ -0,0 +1,343 @@
+use crate::data::*;
+use crate::error::{Error, Result};
+use crate::types::*;
+
+/// A train journey through some stations of a line, with the pdfs of the
+/// passengers in the train computed for every station
+#[derive(Clone, Debug)]
+pub struct Journey {
+    line: String,
+    direction: Direction,
+    stations: Vec<StationStairs>,
+    stair_model: StairModel,
+    grid: SampleGrid,
+    loads: Vec<StationLoad>,
+    boarder_props: Vec<f64>,
+    alighter_props: Vec<f64>,
+    destination_flows: Vec<Vec<f64>>,
+    /// the capacity of the train, if the densities are dispersed
+    dispersion: Option<Dispersion>,
+    pdfs: Vec<Pdf>,
+    densities: Vec<StationDensities>,
+}
+
+impl Journey {
+    /// The stations can be in any order, they are sorted in the order of
+    /// travel. Every station needs a map in the maps directory of the
+    /// dataset. The stair model is validated
+    pub fn new(
+        dataset: &Dataset,
+        line: &str,
+        stations: &[&str],
+        direction: Direction,
+        stair_model: StairModel,
+        od_rows: Option<&[OdRow]>,
+        grid: SampleGrid,
+    ) -> Result<Self> {
+        stair_model.validate()?;
+        let (line_name, rows) =
+            journey_rows(dataset, line, stations, direction)?;
+        let stations = order_stations(&rows, stations);
+        let all_station_stairs =
+            read_station_stairs(dataset, &stations, direction)?;
+        let loads = parse_station_loads(dataset, &line_name, &rows, direction)?;
+        let boarder_props = calc_proportion_of_boarders(&loads);
+        let alighter_props = calc_proportion_of_alighters(&loads);
+        let destination_flows =
+            calc_destination_flows(&stations, &loads, od_rows);
+
+        let mut journey = Self {
+            line: line_name,
+            direction,
+            stations: all_station_stairs,
+            stair_model,
+            grid,
+            loads,
+            boarder_props,
+            alighter_props,
+            destination_flows,
+            dispersion: None,
+            pdfs: vec![],
+            densities: vec![],
+        };
+        journey.compute_densities()?;
+        Ok(journey)
+    }
+
+    fn compute_densities(&mut self) -> Result<()> {
+        let (pdfs, densities) = make_densities_for_all_stations(
+            &self.stations,
+            &self.boarder_props,
+            &self.alighter_props,
+            &self.destination_flows,
+            &self.stair_model,
+            &self.grid,
+            self.dispersion.as_ref(),
+        )?
+        .into_iter()
+        .unzip();
+        self.pdfs = pdfs;
+        self.densities = densities;
+        Ok(())
+    }
+
+    /// Recomputes the densities so that passengers spread out to
+    /// neighbouring positions when the train is fuller than the capacity per
+    /// metre of the formation, instead of piling up near the stairs. Fails if
+    /// the passengers leaving a station do not fit in the trains, or in the
+    /// part of the train covered by the sample grid
+    pub fn with_dispersion(mut self, formation: &Formation) -> Result<Self> {
+        let capacity =
+            formation.cars as f64 * formation.capacity * formation.trains;
+        // the capacity per metre in the units of the pdfs, which are per
+        // proportion of the platform and of the passengers in the train
+        let per_metre = formation.capacity_per_metre()
+            * formation.platform_length
+            * formation.trains;
+        let capacities = self
+            .stations
+            .iter()
+            .zip(&self.loads)
+            .map(|(station, load)| {
+                if load.onboard <= 0 {
+                    Ok(f64::INFINITY)
+                } else if load.onboard as f64 >= capacity {
+                    Err(Error::OverCapacity {
+                        station: station.station_name.clone(),
+                        onboard: load.onboard,
+                        capacity,
+                    })
+                } else {
+                    Ok(per_metre / load.onboard as f64)
+                }
+            })
+            .collect::<Result<_>>()?;
+        self.dispersion = Some(Dispersion {
+            capacities,
+            train: formation.train_bounds(),
+        });
+        self.compute_densities()?;
+        Ok(self)
+    }
+
+    /// Recomputes the densities with another stair model, without reading
+    /// the data again. The stair model is validated
+    pub fn with_stair_model(mut self, stair_model: StairModel) -> Result<Self> {
+        stair_model.validate()?;
+        self.stair_model = stair_model;
+        self.compute_densities()?;
+        Ok(self)
+    }
+
+    /// the name of the line in the link load data, which the line given to
+    /// `new` may only be a variant of
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// the stations in the order of travel
+    pub fn stations(&self) -> &[StationStairs] {
+        &self.stations
+    }
+
+    pub fn station_index(&self, station: &str) -> Option<usize> {
+        self.stations.iter().position(|s| s.station_name == station)
+    }
+
+    pub fn stair_model(&self) -> &StairModel {
+        &self.stair_model
+    }
+
+    pub fn grid(&self) -> &SampleGrid {
+        &self.grid
+    }
+
+    /// the passenger counts of every station from the link load data
+    pub fn loads(&self) -> &[StationLoad] {
+        &self.loads
+    }
+
+    /// p^b for every station
+    pub fn boarder_props(&self) -> &[f64] {
+        &self.boarder_props
+    }
+
+    /// p^a for every station
+    pub fn alighter_props(&self) -> &[f64] {
+        &self.alighter_props
+    }
+
+    /// f for every station
+    pub fn destination_flows(&self) -> &[Vec<f64>] {
+        &self.destination_flows
+    }
+
+    /// m for every station
+    pub fn pdfs(&self) -> &[Pdf] {
+        &self.pdfs
+    }
+
+    /// m, the pdf of passengers in the train after the station
+    pub fn density_after(&self, station: usize) -> &Pdf {
+        &self.pdfs[station]
+    }
+
+    /// the cdf of m, the passengers in the train after the station
+    pub fn cdf_after(&self, station: usize) -> Cdf {
+        self.pdfs[station].cdf()
+    }
+
+    /// the cdf of b, the passengers boarding the train at the station
+    pub fn boarding_cdf(&self, station: usize) -> Cdf {
+        self.densities[station].boarding_pdf.cdf()
+    }
+
+    /// the intermediate densities that m of the station was made from
+    pub fn densities(&self, station: usize) -> &StationDensities {
+        &self.densities[station]
+    }
+
+    /// b, the pdf of passengers boarding the train at the station
+    pub fn boarding_density(&self, station: usize) -> &Pdf {
+        &self.densities[station].boarding_pdf
+    }
+
+    /// r, the pdf of passengers that stayed in the train at the station
+    pub fn remaining_density(&self, station: usize) -> &Pdf {
+        &self.densities[station].remaining_pdf
+    }
+
+    /// m before it was dispersed, the same as m if the journey was not
+    /// dispersed
+    pub fn mixture_density(&self, station: usize) -> &Pdf {
+        &self.densities[station].mixture_pdf
+    }
+
+    /// E, the pdf of passengers close to the stairs of the station
+    pub fn stair_density(&self, station: usize) -> &Pdf {
+        &self.densities[station].stair_pdf
+    }
+
+    /// the components of S for a stair of the station, weighted by its traffic
+    pub fn stair_components(
+        &self,
+        station: usize,
+        stair: usize,
+    ) -> &[(f64, StairComponents)] {
+        &self.densities[station].stair_components[stair]
+    }
+
+    /// the expected passengers in every car of the train after the station
+    pub fn car_loads(
+        &self,
+        station: usize,
+        formation: &Formation,
+    ) -> Vec<CarLoad> {
+        calc_car_loads(
+            &self.pdfs[station],
+            formation,
+            self.loads[station].onboard,
+        )
+    }
+
+    /// the passengers per metre of the platform in every train after the
+    /// station, to compare against the capacity per metre of the cars
+    pub fn passengers_per_metre(
+        &self,
+        station: usize,
+        formation: &Formation,
+    ) -> Pdf {
+        calc_passengers_per_metre(
+            &self.pdfs[station],
+            formation,
+            self.loads[station].onboard,
+        )
+    }
+}
+
+/// the journey of the README on the bundled data, for the tests
+#[cfg(test)]
+pub(crate) fn test_journey(stair_model: StairModel) -> Journey {
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    Journey::new(
+        &read_dataset(root).unwrap(),
+        "中央本線",
+        &["東京", "神田", "御茶ノ水", "四ッ谷"],
+        Direction::Down,
+        stair_model,
+        None,
+        SampleGrid::default(),
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispersion_rejects_trains_over_capacity() {
+        let formation = Formation {
+            trains: 30.0,
+            ..Formation::default()
+        };
+        match test_journey(StairModel::default()).with_dispersion(&formation) {
+            Err(Error::OverCapacity { station, .. }) => {
+                assert_eq!(station, "東京")
+            }
+            result => panic!("{:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn keeps_the_name_of_the_line_in_the_data() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let journey = Journey::new(
+            &read_dataset(root).unwrap(),
+            "中央本",
+            &["東京", "神田"],
+            Direction::Down,
+            StairModel::default(),
+            None,
+            SampleGrid::default(),
+        )
+        .unwrap();
+        assert_eq!(journey.line(), "中央本線");
+    }
+
+    #[test]
+    fn dispersion_rejects_trains_shorter_than_needed() {
+        let formation = Formation {
+            cars: 5,
+            ..Formation::default()
+        };
+        let journey = test_journey(StairModel::default());
+        match journey.with_dispersion(&formation) {
+            Err(Error::OverCapacity { station, .. }) => {
+                assert_eq!(station, "御茶ノ水")
+            }
+            result => panic!("{:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn dispersion_keeps_the_cars_under_capacity() {
+        // some cars are over capacity before dispersing
+        let formation = Formation {
+            trains: 250.0,
+            ..Formation::default()
+        };
+        let journey = test_journey(StairModel::default())
+            .with_dispersion(&formation)
+            .unwrap();
+        for station in 0..journey.stations().len() {
+            for load in journey.car_loads(station, &formation) {
+                assert!(load.load_factor <= 1.0 + 1e-6, "{:?}", load);
+            }
+        }
+    }
+}
@@ -0,0 +1,15 @@
+#![warn(clippy::all)]
+pub mod calibration;
+pub mod data;
+pub mod diagnostics;
+pub mod error;
+pub mod evaluation;
+pub mod export;
+pub mod journey;
+pub mod plot;
+pub mod recommend;
+pub mod sensitivity;
+pub mod simulation;
+pub mod types;
+
+pub use journey::Journey;
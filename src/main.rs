@@ -1,48 +1,703 @@
 #![warn(clippy::all)]
-mod data;
-mod plot;
-mod types;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use train_passenger_distribution::calibration::*;
+use train_passenger_distribution::data::*;
+use train_passenger_distribution::diagnostics::*;
+use train_passenger_distribution::evaluation::*;
+use train_passenger_distribution::export::*;
+use train_passenger_distribution::plot::*;
+use train_passenger_distribution::recommend::*;
+use train_passenger_distribution::sensitivity::*;
+use train_passenger_distribution::simulation::*;
+use train_passenger_distribution::types::*;
+use train_passenger_distribution::Journey;
 
-use data::*;
-use plot::*;
+#[derive(Parser)]
+#[command(about = "Models the distribution of passengers inside a train")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let stations = vec!["東京", "神田", "御茶ノ水", "四ッ谷"];
-    let all_station_stairs = read_station_stairs(stations.clone());
-    let boarder_props = calc_proportion_of_boarders(&stations);
-    dbg!(&boarder_props);
+#[derive(Subcommand)]
+enum Command {
+    /// Run the model, make every plot and export the densities
+    Run(JourneyArgs),
+    /// Plot the pdf after every station, one station per row
+    PlotStations(JourneyArgs),
+    /// Plot the pdf after every station in the same chart
+    PlotTogether(JourneyArgs),
+    /// Plot the cdf after every station in the same chart
+    PlotCdf(JourneyArgs),
+    /// Print the positions with a proportion of the passengers in front of
+    /// them, after every station and of the boarders of every station
+    Quantiles {
+        #[command(flatten)]
+        args: JourneyArgs,
+        /// comma separated proportions between 0 and 1
+        #[arg(long, value_delimiter = ',', default_value = "0.25,0.5,0.75")]
+        at: Vec<f64>,
+    },
+    /// Plot the step-by-step breakdown of the pdf after a station
+    PlotBreakdown {
+        #[command(flatten)]
+        args: JourneyArgs,
+        /// must not be the first station of the journey
+        #[arg(long, required_unless_present = "all", conflicts_with = "all")]
+        station: Option<String>,
+        /// plot a breakdown for every station after the first one
+        #[arg(long)]
+        all: bool,
+    },
+    /// Print the expected share, number of passengers and load factor of
+    /// every car after every station
+    Cars(JourneyArgs),
+    /// Plot the passengers per metre after every station against the
+    /// capacity of the cars
+    PlotCrowding(JourneyArgs),
+    /// Rank the doors of the train by how crowded they are after a station
+    Recommend {
+        #[command(flatten)]
+        args: JourneyArgs,
+        /// the station to board at
+        #[arg(long)]
+        station: String,
+        /// the station to alight at, to average the crowding over the ride
+        #[arg(long)]
+        destination: Option<String>,
+        /// the number of doors to recommend
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+    },
+    /// Print the mass, mode, mean and variance of m and b of every station,
+    /// and warn about lost mass and stairs outside the platform
+    Diagnose(JourneyArgs),
+    /// Simulate discrete passengers through the journey and compare them
+    /// with the pdfs
+    Simulate {
+        #[command(flatten)]
+        args: JourneyArgs,
+        /// the passengers boarding at the first station
+        #[arg(long, default_value_t = 10_000)]
+        passengers: usize,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Fit the stair model to observed shares of the passengers in every
+    /// car, and write it to fitted_model.json for --config
+    Calibrate {
+        #[command(flatten)]
+        args: JourneyArgs,
+        /// CSV file of the observed share of every car, with the columns
+        /// station, car and share
+        #[arg(long)]
+        observed: String,
+        #[arg(long, default_value_t = 500)]
+        max_iterations: usize,
+    },
+    /// Compare the predicted share of every car with observed shares, write
+    /// the metrics to evaluation.csv and plot the residuals
+    Evaluate {
+        #[command(flatten)]
+        args: JourneyArgs,
+        /// CSV file of the observed share of every car, with the columns
+        /// station, car and share
+        #[arg(long)]
+        observed: String,
+    },
+    /// Sweep the concentrations and proportions of the stair model one at a
+    /// time and with a Latin hypercube, and write the front share, peak
+    /// density and car shares of every run
+    Sensitivity {
+        #[command(flatten)]
+        args: JourneyArgs,
+        /// the values of every parameter in the one-at-a-time sweep
+        #[arg(long, default_value_t = 11)]
+        steps: usize,
+        /// the stair models in the Latin hypercube
+        #[arg(long, default_value_t = 100)]
+        samples: usize,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// the station of the tornado plot, by default the last one
+        #[arg(long)]
+        station: Option<String>,
+    },
+    /// Export the densities as CSV (densities.csv) and JSON (journey.json)
+    Export(JourneyArgs),
+    /// List the lines in the link load data. Lines sharing a name are
+    /// numbered, eg: 新宿線#2
+    ListLines {
+        /// directory with the data and maps directories
+        #[arg(long, default_value = ".")]
+        data: PathBuf,
+    },
+    /// List the stations of a line, in the down direction
+    ListStations {
+        #[arg(long, default_value = "中央本線")]
+        line: String,
+        /// directory with the data and maps directories
+        #[arg(long, default_value = ".")]
+        data: PathBuf,
+    },
+}
 
-    let pdfs = make_pdfs_for_all_stations(&all_station_stairs, &boarder_props);
+#[derive(Args)]
+struct JourneyArgs {
+    /// directory with the data and maps directories
+    #[arg(long, default_value = ".")]
+    data: PathBuf,
+    #[arg(long, default_value = "中央本線")]
+    line: String,
+    /// up or down
+    #[arg(long, default_value = "down")]
+    direction: Direction,
+    /// comma separated station names
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "東京,神田,御茶ノ水,四ッ谷"
+    )]
+    stations: Vec<String>,
+    /// directory for the plots, created if it does not exist
+    #[arg(long, default_value = "out")]
+    out: PathBuf,
+    /// image format of the plots
+    #[arg(long, value_enum, default_value_t = ImageFormat::Png)]
+    format: ImageFormat,
+    /// JSON file of stair model parameters
+    #[arg(long)]
+    config: Option<String>,
+    /// CSV file of origin-destination data
+    #[arg(long)]
+    od: Option<String>,
+    /// JSON file of the train formation
+    #[arg(long)]
+    formation: Option<String>,
+    /// spread passengers out where the train is over the capacity of the
+    /// formation
+    #[arg(long)]
+    disperse: bool,
+    /// number of points to sample the pdfs at
+    #[arg(long, default_value_t = 101)]
+    resolution: usize,
+    /// the front end of the sampled domain, as a percentage of the platform
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    x_min: f64,
+    /// the back end of the sampled domain, as a percentage of the platform
+    #[arg(long, default_value_t = 100.0, allow_negative_numbers = true)]
+    x_max: f64,
+}
 
-    plot_pdfs("out/out.png", &all_station_stairs, pdfs.clone())?;
-    plot_pdfs_together("out/together.png", &all_station_stairs, pdfs.clone())?;
+#[derive(Clone, Copy, ValueEnum)]
+enum ImageFormat {
+    Png,
+    Svg,
+}
 
-    // TODO: duplicated computation. above should do it then combine it
-    let x: Vec<Vec<(f64, (f64, f64, f64))>> = (1..=100)
-        .map(|x| {
-            all_station_stairs[2]
-                .stair_locations
-                .iter()
-                .map(|stair| {
-                    let div =
-                        all_station_stairs[2].stair_locations.len() as f64;
-                    let (a, b, c) = stair_pdfs_sep(stair, x as f64 / 100.);
-                    (x as f64, (a / div, b / div, c / div))
-                })
-                .collect()
-        })
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Svg => "svg",
+        }
+    }
+}
+
+fn make_journey(
+    args: &JourneyArgs,
+) -> Result<Journey, Box<dyn std::error::Error>> {
+    let stair_model = match &args.config {
+        Some(path) => read_stair_model(path)?,
+        None => StairModel::default(),
+    };
+    let od_rows = match &args.od {
+        Some(path) => Some(read_od_data(path)?),
+        None => None,
+    };
+    let stations: Vec<&str> =
+        args.stations.iter().map(|s| s.as_str()).collect();
+    let journey = Journey::new(
+        &read_dataset(&args.data)?,
+        &args.line,
+        &stations,
+        args.direction,
+        stair_model,
+        od_rows.as_deref(),
+        SampleGrid::new(args.x_min, args.x_max, args.resolution)?,
+    )?;
+    if args.disperse {
+        Ok(journey.with_dispersion(&read_formation_arg(args)?)?)
+    } else {
+        Ok(journey)
+    }
+}
+
+fn read_formation_arg(
+    args: &JourneyArgs,
+) -> Result<Formation, Box<dyn std::error::Error>> {
+    Ok(match &args.formation {
+        Some(path) => read_formation(path)?,
+        None => Formation::default(),
+    })
+}
+
+/// prints the formation the passenger counts and load factors assume
+fn print_formation(args: &JourneyArgs, formation: &Formation) {
+    let source = match &args.formation {
+        Some(path) => path.as_str(),
+        None => "the default, change it with --formation",
+    };
+    eprintln!(
+        "Formation ({}): {} cars of {}m carrying {} passengers each, {} \
+         trains a day",
+        source,
+        formation.cars,
+        formation.car_length,
+        formation.capacity,
+        formation.trains
+    );
+}
+
+/// the path of a file in the output directory
+fn out_path(
+    args: &JourneyArgs,
+    filename: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(&args.out)?;
+    Ok(args.out.join(filename).to_string_lossy().into_owned())
+}
+
+/// the path of a plot in the output directory, with the extension of the
+/// image format
+fn out_file(
+    args: &JourneyArgs,
+    name: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    out_path(args, &format!("{}.{}", name, args.format.extension()))
+}
+
+fn export(
+    journey: &Journey,
+    args: &JourneyArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_densities_csv(&out_path(args, "densities.csv")?, journey)?;
+    write_journey_json(&out_path(args, "journey.json")?, journey)
+}
+
+fn plot_cdfs(
+    journey: &Journey,
+    args: &JourneyArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cdfs: Vec<Cdf> = (0..journey.stations().len())
+        .map(|idx| journey.cdf_after(idx))
         .collect();
+    plot_cdfs_together(
+        &out_file(args, "cdf")?,
+        journey.stations(),
+        &cdfs,
+        journey.grid(),
+    )
+}
 
-    let prev_pdf = &pdfs[1];
-    let this_pdf = &pdfs[2];
+/// the mass lost or gained above which a density is warned about
+const MASS_TOLERANCE: f64 = 0.01;
 
-    plot_stair_pdfs_sep(
-        "out/step-by-step.png",
-        x,
-        &all_station_stairs[2].stair_locations,
-        prev_pdf,
-        this_pdf,
-    )?;
+fn print_diagnostics(journey: &Journey) {
+    println!("station\tcurve\tmass\tmode\tmean\tvariance");
+    let diagnostics = diagnose(journey);
+    for d in &diagnostics {
+        for (curve, stats) in [("m", &d.density), ("b", &d.boarding_density)] {
+            println!(
+                "{}\t{}\t{:.4}\t{:.1}\t{:.1}\t{:.1}",
+                d.station,
+                curve,
+                stats.mass,
+                stats.mode,
+                stats.mean,
+                stats.variance
+            );
+        }
+    }
+    for d in &diagnostics {
+        for (curve, stats) in [("m", &d.density), ("b", &d.boarding_density)] {
+            if stats.mass_error().abs() > MASS_TOLERANCE {
+                eprintln!(
+                    "Warning: {} of {} has a mass of {:.4} on the platform",
+                    curve, d.station, stats.mass
+                );
+            }
+        }
+        for stair in &d.clamped_stairs {
+            eprintln!(
+                "Warning: the stair of {} at {:.1} is outside the platform and was moved to its end",
+                d.station, stair
+            );
+        }
+    }
+}
+
+fn find_station(
+    journey: &Journey,
+    station: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    Ok(journey
+        .station_index(station)
+        .ok_or_else(|| format!("{} is not in the journey", station))?)
+}
+
+fn plot_breakdown(
+    journey: &Journey,
+    args: &JourneyArgs,
+    station: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let idx = find_station(journey, station)?;
+    plot_stair_pdfs_sep(&out_file(args, "step-by-step")?, journey, idx)
+}
 
+/// plots a breakdown for every station after the first one, named after the
+/// station
+fn plot_all_breakdowns(
+    journey: &Journey,
+    args: &JourneyArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (idx, station) in journey.stations().iter().enumerate().skip(1) {
+        let name = format!("step-by-step-{}", station.station_name);
+        plot_stair_pdfs_sep(&out_file(args, &name)?, journey, idx)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli.command) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(command: Command) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Run(args) => {
+            let journey = make_journey(&args)?;
+            println!("station\tboarder_prop\talighter_prop");
+            for ((station, boarder_prop), alighter_prop) in journey
+                .stations()
+                .iter()
+                .zip(journey.boarder_props())
+                .zip(journey.alighter_props())
+            {
+                println!(
+                    "{}\t{:.4}\t{:.4}",
+                    station.station_name, boarder_prop, alighter_prop
+                );
+            }
+
+            plot_pdfs(
+                &out_file(&args, "out")?,
+                journey.stations(),
+                journey.pdfs(),
+                journey.grid(),
+            )?;
+            plot_pdfs_together(
+                &out_file(&args, "together")?,
+                journey.stations(),
+                journey.pdfs(),
+                journey.grid(),
+            )?;
+            plot_cdfs(&journey, &args)?;
+            plot_all_breakdowns(&journey, &args)?;
+            export(&journey, &args)?;
+        }
+        Command::PlotStations(args) => {
+            let journey = make_journey(&args)?;
+            plot_pdfs(
+                &out_file(&args, "out")?,
+                journey.stations(),
+                journey.pdfs(),
+                journey.grid(),
+            )?;
+        }
+        Command::PlotTogether(args) => {
+            let journey = make_journey(&args)?;
+            plot_pdfs_together(
+                &out_file(&args, "together")?,
+                journey.stations(),
+                journey.pdfs(),
+                journey.grid(),
+            )?;
+        }
+        Command::PlotCdf(args) => {
+            let journey = make_journey(&args)?;
+            plot_cdfs(&journey, &args)?;
+        }
+        Command::Quantiles { args, at } => {
+            if let Some(p) = at.iter().find(|p| !(0.0..=1.0).contains(*p)) {
+                return Err(format!(
+                    "proportions must be between 0 and 1, got {}",
+                    p
+                )
+                .into());
+            }
+            let journey = make_journey(&args)?;
+            println!("station\tproportion\tm_xpos\tb_xpos");
+            for (idx, station) in journey.stations().iter().enumerate() {
+                let cdf = journey.cdf_after(idx);
+                let boarding_cdf = journey.boarding_cdf(idx);
+                for p in &at {
+                    println!(
+                        "{}\t{}\t{:.1}\t{:.1}",
+                        station.station_name,
+                        p,
+                        cdf.quantile(*p),
+                        boarding_cdf.quantile(*p)
+                    );
+                }
+            }
+        }
+        Command::PlotBreakdown { args, station, .. } => {
+            let journey = make_journey(&args)?;
+            match station {
+                Some(station) => plot_breakdown(&journey, &args, &station)?,
+                None => plot_all_breakdowns(&journey, &args)?,
+            }
+        }
+        Command::Cars(args) => {
+            let journey = make_journey(&args)?;
+            let formation = read_formation_arg(&args)?;
+            print_formation(&args, &formation);
+            println!("station\tcar\tshare\tcount\tload_factor");
+            for (idx, station) in journey.stations().iter().enumerate() {
+                for car in journey.car_loads(idx, &formation) {
+                    println!(
+                        "{}\t{}\t{:.4}\t{:.0}\t{:.2}",
+                        station.station_name,
+                        car.car,
+                        car.share,
+                        car.count,
+                        car.load_factor
+                    );
+                }
+            }
+        }
+        Command::PlotCrowding(args) => {
+            let journey = make_journey(&args)?;
+            let formation = read_formation_arg(&args)?;
+            print_formation(&args, &formation);
+            plot_crowding(&out_file(&args, "crowding")?, &journey, &formation)?;
+        }
+        Command::Recommend {
+            args,
+            station,
+            destination,
+            top,
+        } => {
+            let journey = make_journey(&args)?;
+            let station = find_station(&journey, &station)?;
+            let destination = match destination {
+                Some(destination) => {
+                    Some(find_station(&journey, &destination)?)
+                }
+                None => None,
+            };
+            let formation = read_formation_arg(&args)?;
+            print_formation(&args, &formation);
+            let recommendations = recommend_doors(
+                &journey,
+                station,
+                destination,
+                &formation,
+                top,
+            )?;
+            println!(
+                "rank\tcar\tdoor\txpos\tpassengers_per_metre\tload_factor"
+            );
+            for (rank, r) in recommendations.iter().enumerate() {
+                println!(
+                    "{}\t{}\t{}\t{:.1}\t{:.2}\t{:.2}",
+                    rank + 1,
+                    r.door.car,
+                    r.door.door,
+                    r.door.position,
+                    r.passengers_per_metre,
+                    r.load_factor
+                );
+            }
+        }
+        Command::Diagnose(args) => {
+            print_diagnostics(&make_journey(&args)?);
+        }
+        Command::Simulate {
+            args,
+            passengers,
+            seed,
+        } => {
+            let journey = make_journey(&args)?;
+            let config = SimulationConfig { passengers, seed };
+            let simulated: Vec<Pdf> = simulate(&journey, &config)
+                .iter()
+                .map(|positions| kde(positions, journey.grid()))
+                .collect();
+            println!("station\tmean_abs_diff\tmax_abs_diff");
+            for ((station, pdf), sim) in journey
+                .stations()
+                .iter()
+                .zip(journey.pdfs())
+                .zip(&simulated)
+            {
+                let diffs: Vec<f64> = pdf
+                    .densities()
+                    .zip(sim.densities())
+                    .map(|(a, b)| (a - b).abs())
+                    .collect();
+                println!(
+                    "{}\t{:.4}\t{:.4}",
+                    station.station_name,
+                    diffs.iter().sum::<f64>() / diffs.len() as f64,
+                    diffs.iter().copied().fold(0.0, f64::max)
+                );
+            }
+            plot_pdfs_compared(
+                &out_file(&args, "simulation")?,
+                journey.stations(),
+                journey.pdfs(),
+                &simulated,
+                "simulation",
+                journey.grid(),
+            )?;
+        }
+        Command::Calibrate {
+            args,
+            observed,
+            max_iterations,
+        } => {
+            let journey = make_journey(&args)?;
+            let calibration = calibrate(
+                &journey,
+                &read_formation_arg(&args)?,
+                &read_observed_cars(&observed)?,
+                max_iterations,
+            )?;
+            let model = &calibration.stair_model;
+            println!("parameter\tvalue");
+            println!("prop_normal_far\t{:.4}", model.prop_normal_far);
+            println!("prop_normal_close\t{:.4}", model.prop_normal_close);
+            println!("prop_uniform\t{:.4}", model.prop_uniform);
+            println!("far_concentration\t{:.2}", model.far_concentration);
+            println!("close_concentration\t{:.2}", model.close_concentration);
+            println!();
+            println!("fit\tcross_entropy\trmse\tmax_abs_error");
+            for (name, fit) in [
+                ("initial", &calibration.initial_fit),
+                ("fitted", &calibration.fit),
+            ] {
+                println!(
+                    "{}\t{:.4}\t{:.4}\t{:.4}",
+                    name, fit.cross_entropy, fit.rmse, fit.max_abs_error
+                );
+            }
+            println!("\n{} iterations", calibration.iterations);
+            serde_json::to_writer_pretty(
+                std::fs::File::create(out_path(&args, "fitted_model.json")?)?,
+                model,
+            )?;
+        }
+        Command::Evaluate { args, observed } => {
+            let journey = make_journey(&args)?;
+            let formation = read_formation_arg(&args)?;
+            let evaluations = evaluate(
+                &journey,
+                &formation,
+                &read_observed_cars(&observed)?,
+            )?;
+            println!(
+                "station\tkl_divergence\twasserstein\trmse\tmax_abs_error"
+            );
+            for e in &evaluations {
+                println!(
+                    "{}\t{:.4}\t{:.2}\t{:.4}\t{:.4}",
+                    e.station,
+                    e.kl_divergence,
+                    e.wasserstein,
+                    e.rmse,
+                    e.max_abs_error
+                );
+            }
+            write_evaluation_csv(
+                &out_path(&args, "evaluation.csv")?,
+                &evaluations,
+            )?;
+            plot_residuals(
+                &out_file(&args, "residuals")?,
+                &evaluations,
+                &formation,
+            )?;
+        }
+        Command::Sensitivity {
+            args,
+            steps,
+            samples,
+            seed,
+            station,
+        } => {
+            let journey = make_journey(&args)?;
+            let formation = read_formation_arg(&args)?;
+            let station = match station {
+                Some(station) => find_station(&journey, &station)?,
+                None => journey.stations().len() - 1,
+            };
+            let sweep = one_at_a_time(&journey, &formation, steps)?;
+            write_sensitivity_csv(
+                &out_path(&args, "sensitivity_oat.csv")?,
+                &sweep,
+            )?;
+            write_sensitivity_csv(
+                &out_path(&args, "sensitivity_lhs.csv")?,
+                &latin_hypercube(&journey, &formation, samples, seed)?,
+            )?;
+            plot_sensitivity(
+                &out_file(&args, "sensitivity")?,
+                &sweep,
+                journey.stair_model(),
+            )?;
+            plot_tornado(
+                &out_file(&args, "tornado")?,
+                &sweep,
+                &station_metrics(&journey, &formation),
+                station,
+            )?;
+        }
+        Command::Export(args) => {
+            let journey = make_journey(&args)?;
+            export(&journey, &args)?;
+        }
+        Command::ListLines { data } => {
+            let dataset = read_dataset(&data)?;
+            for (name, (line, rows)) in
+                line_names(&dataset.lines).iter().zip(&dataset.lines)
+            {
+                // lines sharing a name are told apart by their end stations
+                match (rows.first(), rows.last()) {
+                    (Some(first), Some(last)) if name != line => {
+                        println!("{} ({} - {})", name, &first[0], &last[0])
+                    }
+                    _ => println!("{}", name),
+                }
+            }
+        }
+        Command::ListStations { line, data } => {
+            let dataset = read_dataset(&data)?;
+            let (_, rows) = find_line(&dataset.lines, &line)?;
+            for row in rows {
+                let station = &row[0];
+                if dataset.map_path(station).exists() {
+                    println!("{}", station);
+                } else {
+                    println!("{} (no map)", station);
+                }
+            }
+        }
+    }
     Ok(())
 }
@@ -6,6 +6,54 @@ use plotters::style::Color;
 
 pub const GRAY: RGBColor = RGBColor(100, 100, 100);
 
+/// the seaborn "deep" palette, without its gray
+const DEEP: [RGBColor; 9] = [
+    RGBColor(76, 114, 176),
+    RGBColor(221, 132, 82),
+    RGBColor(85, 168, 104),
+    RGBColor(196, 78, 82),
+    RGBColor(129, 114, 179),
+    RGBColor(147, 120, 96),
+    RGBColor(218, 139, 195),
+    RGBColor(204, 185, 116),
+    RGBColor(100, 181, 205),
+];
+
+/// the viridis colormap, sampled at every 1/8
+const VIRIDIS: [RGBColor; 9] = [
+    RGBColor(68, 1, 84),
+    RGBColor(71, 44, 122),
+    RGBColor(59, 81, 139),
+    RGBColor(44, 113, 142),
+    RGBColor(33, 144, 141),
+    RGBColor(39, 173, 129),
+    RGBColor(92, 200, 99),
+    RGBColor(170, 220, 50),
+    RGBColor(253, 231, 37),
+];
+
+/// n distinct colors. Uses the deep palette if it has enough colors,
+/// otherwise samples viridis evenly so that neighbouring stations have
+/// similar colors
+pub fn palette(n: usize) -> Vec<RGBColor> {
+    if n <= DEEP.len() {
+        return DEEP[..n].to_vec();
+    }
+    (0..n).map(|i| viridis(i as f64 / (n - 1) as f64)).collect()
+}
+
+/// linearly interpolates viridis at t between 0 and 1
+fn viridis(t: f64) -> RGBColor {
+    let pos = t.clamp(0.0, 1.0) * (VIRIDIS.len() - 1) as f64;
+    let lower = (pos.floor() as usize).min(VIRIDIS.len() - 2);
+    let frac = pos - lower as f64;
+    let RGBColor(r1, g1, b1) = VIRIDIS[lower];
+    let RGBColor(r2, g2, b2) = VIRIDIS[lower + 1];
+    let lerp =
+        |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * frac).round() as u8;
+    RGBColor(lerp(r1, r2), lerp(g1, g2), lerp(b1, b2))
+}
+
 pub fn black_stroke() -> ShapeStyle {
     ShapeStyle {
         color: RGBAColor(0, 0, 0, 1.0),
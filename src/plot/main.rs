@@ -1,38 +1,67 @@
+//! Every plot is saved as an SVG if the file name ends in .svg, otherwise as
+//! a bitmap, by `draw_to_file!`
+
+use crate::evaluation::StationEvaluation;
 use crate::plot::colors::*;
 use crate::plot::utils::*;
+use crate::sensitivity::*;
 use crate::types::*;
+use crate::Journey;
+use plotters::coord::Shift;
 use plotters::prelude::*;
 
-pub const COLORS: [RGBColor; 4] = [
-    RGBColor(76, 114, 176),
-    RGBColor(221, 132, 82),
-    RGBColor(85, 168, 104),
-    RGBColor(196, 78, 82),
-];
+/// the font of titles with station names
+const TITLE_FONT: &str = "Hiragino Sans GB W3";
+
+/// the height of the chart of each station
+const STATION_ROW_HEIGHT: u32 = 192;
+
+/// the height of each row in the breakdown plot
+const BREAKDOWN_ROW_HEIGHT: u32 = 250;
 
+/// Plots the pdf after every station, one station per row
 pub fn plot_pdfs(
     filename: &str,
     all_station_stairs: &[StationStairs],
-    pdfs: Vec<Vec<(f64, f64)>>,
+    pdfs: &[Pdf],
+    grid: &SampleGrid,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+    // at least 4 rows high so that short journeys are not squashed
+    let height = STATION_ROW_HEIGHT * pdfs.len().max(4) as u32;
+    draw_to_file!(
+        filename,
+        (1024, height),
+        draw_pdfs(all_station_stairs, pdfs, grid)
+    )
+}
+
+fn draw_pdfs<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    all_station_stairs: &[StationStairs],
+    pdfs: &[Pdf],
+    grid: &SampleGrid,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
     root.fill(&WHITE)?;
 
     let roots = root.split_evenly((pdfs.len(), 1));
+    let colors = palette(pdfs.len());
 
     for ((idx, r), station) in roots.iter().enumerate().zip(all_station_stairs)
     {
-        r.titled(&station.station_name, ("Hiragino Sans GB W3", 20_i32))?;
+        r.titled(&station.station_name, (TITLE_FONT, 20_i32))?;
         let mut chart = basic_chart!(r)
             .margin_top(30_i32)
-            .build_cartesian_2d(-10.0..110.0_f64, 0.0..2.0_f64)
+            .build_cartesian_2d(grid.plot_range(), 0.0..2.0_f64)
             .unwrap();
 
         let mut mesh = chart.configure_mesh();
         let mesh = mesh
             .y_desc("density")
             .axis_desc_style(("sans-serif", 20_i32).into_text_style(r))
-            .light_line_style(&WHITE);
+            .light_line_style(WHITE);
         if idx == pdfs.len() - 1 {
             mesh.x_desc("xpos").draw()?;
         } else {
@@ -41,15 +70,15 @@ pub fn plot_pdfs(
 
         for (i, pdf) in pdfs.iter().enumerate() {
             let color = if i == idx {
-                let c: RGBColor = COLORS[i];
-                c.stroke_width(2)
+                colors[i].stroke_width(2)
             } else {
                 GRAY.filled()
             };
-            chart.draw_series(LineSeries::new(pdf.clone(), color))?;
+            chart.draw_series(LineSeries::new(pdf.iter().copied(), color))?;
         }
 
-        let modifier = (((192 * idx) as f32) - 0.5) as i32;
+        // the stairs are mapped to the whole plot but drawn in the row
+        let modifier = r.get_base_pixel().1;
         plot_platform_bounds(&chart, r, modifier, 30)?;
 
         for stair in &station.stair_locations {
@@ -57,38 +86,70 @@ pub fn plot_pdfs(
         }
     }
 
+    root.present()?;
     Ok(())
 }
 
+/// Plots the step-by-step breakdown of the pdf after a station of the journey,
+/// from the pdf after the previous station
 pub fn plot_stair_pdfs_sep(
     filename: &str,
-    pdfs: Vec<Vec<(f64, (f64, f64, f64))>>,
-    stairs: &[f64],
-    prev_pdf: &[(f64, f64)],
-    this_pdf: &[(f64, f64)],
+    journey: &Journey,
+    idx: usize,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let station = journey
+        .stations()
+        .get(idx)
+        .ok_or_else(|| format!("the journey has no station #{}", idx))?;
+    if idx == 0 {
+        return Err(format!(
+            "{} is the first station, so there is nothing to break down",
+            station.station_name
+        )
+        .into());
+    }
+    let n_rows = station.stair_locations.len() + 2;
+    draw_to_file!(
+        filename,
+        (1024 * 2, BREAKDOWN_ROW_HEIGHT * n_rows as u32),
+        draw_stair_pdfs_sep(journey, idx)
+    )
+}
+
+fn draw_stair_pdfs_sep<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    journey: &Journey,
+    idx: usize,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let station = &journey.stations()[idx];
+    let name = &station.station_name;
+    let prev_name = &journey.stations()[idx - 1].station_name;
+    let components = &journey.densities(idx).stair_components;
+    let grid = journey.grid();
+    let stairs = &station.stair_locations;
     let n_stairs = stairs.len();
 
-    let root =
-        BitMapBackend::new(filename, (1024 * 2, 1500)).into_drawing_area();
     root.fill(&WHITE)?;
 
     let (left, right) = root.split_horizontally(1024_i32);
     let right_roots = right.split_evenly((n_stairs + 2, 1));
     let roots = left.split_evenly((n_stairs + 2, 1));
 
-    let as_: [fn((f64, f64, f64)) -> f64; 3] =
+    let as_: [fn(StairComponents) -> f64; 3] =
         [|ys| ys.0, |ys| ys.1, |ys| ys.2];
 
-    for (idx, r) in roots.iter().enumerate().take(n_stairs) {
+    for (stair, r) in roots.iter().enumerate().take(n_stairs) {
         r.titled(
-            &format!("Passengers boarding at Ochanomizu stair #{}", idx + 1),
-            ("sans-serif", 30_i32),
+            &format!("Passengers boarding at {} stair #{}", name, stair + 1),
+            (TITLE_FONT, 30_i32),
         )?;
-        let mut chart = chart_with_mesh!(r, 0.0..1.5_f64);
+        let mut chart = chart_with_mesh!(r, grid.plot_range(), 0.0..1.5_f64);
         let labels = ["beta_far", "beta_close", "uniform"];
-        for ((a, color), label) in as_.iter().zip(COLORS).zip(labels) {
-            let pdf = &pdfs.iter().map(|x| x[idx]).map(|(x, ys)| (x, a(ys)));
+        for ((a, color), label) in as_.iter().zip(palette(3)).zip(labels) {
+            let pdf = &components[stair].iter().map(|(x, ys)| (*x, a(*ys)));
             chart
                 .draw_series(LineSeries::new(
                     pdf.clone(),
@@ -101,65 +162,50 @@ pub fn plot_stair_pdfs_sep(
 
         plot_platform_bounds(&chart, r, 0, 35).unwrap();
 
-        plot_stairs(r, &chart, stairs[idx], 0, 35).unwrap();
+        plot_stairs(r, &chart, stairs[stair], 0, 35).unwrap();
 
-        if idx == 0 {
+        if stair == 0 {
             add_legend!(chart, "sans-serif").unwrap();
         }
     }
 
-    let sum_pdfs: Vec<Vec<(f64, f64)>> = right_roots
-        .iter()
-        .enumerate()
-        .take(n_stairs)
-        .map(|(idx, r)| {
-            r.titled(
-                &format!(
-                    "All passengers boarding at Ochanomizu stair #{} (S{}/{})",
-                    idx + 1,
-                    SUBSCRIPTS[idx],
-                    n_stairs
-                ),
-                ("sans-serif", 30_i32),
-            )
+    for (stair, r) in right_roots.iter().enumerate().take(n_stairs) {
+        r.titled(
+            &format!(
+                "All passengers boarding at {} stair #{} (S{}×{:.2})",
+                name,
+                stair + 1,
+                subscript(stair + 1),
+                station.stair_weights[stair]
+            ),
+            (TITLE_FONT, 30_i32),
+        )?;
+        let mut chart = chart_with_mesh!(r, grid.plot_range(), 0.0..2.5_f64);
+        chart
+            .draw_series(LineSeries::new(
+                components[stair]
+                    .iter()
+                    .map(|(x, (a, b, c))| (*x, a + b + c)),
+                BLUE.stroke_width(2),
+            ))
             .unwrap();
-            let mut chart = chart_with_mesh!(r, 0.0..0.6_f64);
-            let sum_pdf = &pdfs
-                .iter()
-                .map(|x| x[idx])
-                .map(|(x, (a, b, c))| (x, (a + b + c) / stairs.len() as f64));
-
-            chart
-                .draw_series(LineSeries::new(
-                    sum_pdf.clone(),
-                    BLUE.stroke_width(2),
-                ))
-                .unwrap();
-
-            plot_platform_bounds(&chart, r, 0, 35).unwrap();
-
-            plot_stairs(r, &chart, stairs[idx], 0, 35).unwrap();
 
-            sum_pdf.clone().collect()
-        })
-        .collect();
+        plot_platform_bounds(&chart, r, 0, 35).unwrap();
 
-    let all_sum_pdf =
-        sum_pdfs.iter().skip(1).fold(sum_pdfs[0].clone(), |acc, v| {
-            acc.iter()
-                .zip(v)
-                .map(|((x1, y1), (_, y2))| (*x1, y1 + y2))
-                .collect()
-        });
+        plot_stairs(r, &chart, stairs[stair], 0, 35).unwrap();
+    }
 
     let r = &right_roots[n_stairs];
     r.titled(
-        &format!("All boarders at Ochanomizu (b{})", SUBSCRIPTS[1]),
-        ("sans-serif", 30_i32),
+        &format!("All boarders at {} (b{})", name, subscript(idx)),
+        (TITLE_FONT, 30_i32),
     )?;
-    let mut chart = chart_with_mesh!(r, 0.0..0.6_f64);
+    let mut chart = chart_with_mesh!(r, grid.plot_range(), 0.0..2.5_f64);
     chart
-        .draw_series(LineSeries::new(all_sum_pdf, BLUE.stroke_width(2)))
+        .draw_series(LineSeries::new(
+            journey.boarding_density(idx).iter().copied(),
+            BLUE.stroke_width(2),
+        ))
         .unwrap();
 
     plot_platform_bounds(&chart, r, 0, 35).unwrap();
@@ -170,24 +216,30 @@ pub fn plot_stair_pdfs_sep(
 
     let r = &roots[n_stairs];
     r.titled(
-        &format!("PDF of Kanda (m{})", SUBSCRIPTS[0]),
-        ("sans-serif", 30_i32),
+        &format!("PDF of {} (m{})", prev_name, subscript(idx - 1)),
+        (TITLE_FONT, 30_i32),
     )?;
-    let mut chart = chart_with_mesh!(r, 0.0..2.0_f64);
+    let mut chart = chart_with_mesh!(r, grid.plot_range(), 0.0..2.0_f64);
     chart
-        .draw_series(LineSeries::new(prev_pdf.to_owned(), BLUE.stroke_width(2)))
+        .draw_series(LineSeries::new(
+            journey.density_after(idx - 1).iter().copied(),
+            BLUE.stroke_width(2),
+        ))
         .unwrap();
 
     plot_platform_bounds(&chart, r, 0, 35).unwrap();
 
     let r = &roots[n_stairs + 1];
     r.titled(
-        &format!("PDF of Ochanomizu (m{})", SUBSCRIPTS[1]),
-        ("sans-serif", 30_i32),
+        &format!("PDF of {} (m{})", name, subscript(idx)),
+        (TITLE_FONT, 30_i32),
     )?;
-    let mut chart = chart_with_mesh!(r, 0.0..2.0_f64);
+    let mut chart = chart_with_mesh!(r, grid.plot_range(), 0.0..2.0_f64);
     chart
-        .draw_series(LineSeries::new(this_pdf.to_owned(), BLUE.stroke_width(2)))
+        .draw_series(LineSeries::new(
+            journey.density_after(idx).iter().copied(),
+            BLUE.stroke_width(2),
+        ))
         .unwrap();
 
     plot_platform_bounds(&chart, r, 0, 35).unwrap();
@@ -196,40 +248,494 @@ pub fn plot_stair_pdfs_sep(
         plot_stairs(r, &chart, *stair, 0, 35).unwrap();
     }
 
+    root.present()?;
     Ok(())
 }
 
+/// Plots the pdf after every station in the same chart
 pub fn plot_pdfs_together(
     filename: &str,
     all_station_stairs: &[StationStairs],
-    pdfs: Vec<Vec<(f64, f64)>>,
+    pdfs: &[Pdf],
+    grid: &SampleGrid,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let curves: Vec<&[(f64, f64)]> = pdfs.iter().map(|pdf| &**pdf).collect();
+    draw_to_file!(
+        filename,
+        (1024, 768),
+        draw_together(
+            all_station_stairs,
+            &curves,
+            grid,
+            0.0..2.0,
+            "density",
+            SeriesLabelPosition::UpperRight
+        )
+    )
+}
+
+/// Plots the pdf after every station against another estimate of it, such as
+/// from a simulation, one station per row
+pub fn plot_pdfs_compared(
+    filename: &str,
+    all_station_stairs: &[StationStairs],
+    pdfs: &[Pdf],
+    others: &[Pdf],
+    other_label: &str,
+    grid: &SampleGrid,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let height = STATION_ROW_HEIGHT * pdfs.len().max(4) as u32;
+    draw_to_file!(
+        filename,
+        (1024, height),
+        draw_pdfs_compared(all_station_stairs, pdfs, others, other_label, grid)
+    )
+}
+
+fn draw_pdfs_compared<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    all_station_stairs: &[StationStairs],
+    pdfs: &[Pdf],
+    others: &[Pdf],
+    other_label: &str,
+    grid: &SampleGrid,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    let roots = root.split_evenly((pdfs.len(), 1));
+    let colors = palette(pdfs.len());
+
+    for (((idx, r), station), (pdf, other)) in roots
+        .iter()
+        .enumerate()
+        .zip(all_station_stairs)
+        .zip(pdfs.iter().zip(others))
+    {
+        r.titled(&station.station_name, (TITLE_FONT, 20_i32))?;
+        let mut chart = basic_chart!(r)
+            .margin_top(30_i32)
+            .build_cartesian_2d(grid.plot_range(), 0.0..2.0_f64)
+            .unwrap();
+
+        let mut mesh = chart.configure_mesh();
+        let mesh = mesh
+            .y_desc("density")
+            .axis_desc_style(("sans-serif", 20_i32).into_text_style(r))
+            .light_line_style(WHITE);
+        if idx == pdfs.len() - 1 {
+            mesh.x_desc("xpos").draw()?;
+        } else {
+            mesh.draw()?;
+        }
+
+        chart
+            .draw_series(LineSeries::new(
+                pdf.iter().copied(),
+                colors[idx].stroke_width(2),
+            ))?
+            .label("model")
+            .add_legend_icon(colors[idx]);
+        chart
+            .draw_series(LineSeries::new(
+                other.iter().copied(),
+                BLACK.stroke_width(1),
+            ))?
+            .label(other_label)
+            .add_legend_icon(BLACK);
+
+        let modifier = r.get_base_pixel().1;
+        plot_platform_bounds(&chart, r, modifier, 30)?;
+        add_legend!(chart, "sans-serif")?;
+    }
+
+    root.present()?;
+    Ok(())
+}
+
+/// Plots the cdf after every station in the same chart
+pub fn plot_cdfs_together(
+    filename: &str,
+    all_station_stairs: &[StationStairs],
+    cdfs: &[Cdf],
+    grid: &SampleGrid,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+    let curves: Vec<&[(f64, f64)]> = cdfs.iter().map(|cdf| &**cdf).collect();
+    draw_to_file!(
+        filename,
+        (1024, 768),
+        draw_together(
+            all_station_stairs,
+            &curves,
+            grid,
+            0.0..1.05,
+            "cumulative proportion",
+            // the cdfs are close to 0 at the front and close to 1 at the back
+            SeriesLabelPosition::LowerRight
+        )
+    )
+}
+
+fn draw_together<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    all_station_stairs: &[StationStairs],
+    curves: &[&[(f64, f64)]],
+    grid: &SampleGrid,
+    y_range: std::ops::Range<f64>,
+    y_desc: &str,
+    legend_position: SeriesLabelPosition,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
     root.fill(&WHITE)?;
 
     let mut chart = basic_chart!(&root)
         .margin_top(30_i32)
-        .build_cartesian_2d(-10.0..110.0_f64, 0.0..2.0_f64)
+        .build_cartesian_2d(grid.plot_range(), y_range)
         .unwrap();
 
     chart
         .configure_mesh()
         .x_desc("xpos")
-        .y_desc("density")
+        .y_desc(y_desc)
         .axis_desc_style(("sans-serif", 20_i32).into_text_style(&root))
-        .light_line_style(&WHITE)
+        .light_line_style(WHITE)
         .draw()?;
 
-    for ((pdf, color), station) in
-        pdfs.iter().zip(COLORS).zip(all_station_stairs)
+    for ((curve, color), station) in curves
+        .iter()
+        .zip(palette(curves.len()))
+        .zip(all_station_stairs)
     {
         chart
-            .draw_series(LineSeries::new(pdf.clone(), color.stroke_width(3)))?
+            .draw_series(LineSeries::new(
+                curve.iter().copied(),
+                color.stroke_width(3),
+            ))?
             .label(&station.station_name)
             .add_legend_icon(color);
     }
     plot_platform_bounds(&chart, &root, 0, 35)?;
-    add_legend!(chart, "Hiragino Sans GB W3")?;
+    add_legend!(chart, TITLE_FONT, legend_position)?;
+
+    root.present()?;
+    Ok(())
+}
+
+/// Plots the passengers per metre after every station, one station per row,
+/// against the capacity per metre of the cars
+pub fn plot_crowding(
+    filename: &str,
+    journey: &Journey,
+    formation: &Formation,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let n_stations = journey.stations().len();
+    let height = STATION_ROW_HEIGHT * n_stations.max(4) as u32;
+    draw_to_file!(filename, (1024, height), draw_crowding(journey, formation))
+}
+
+fn draw_crowding<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    journey: &Journey,
+    formation: &Formation,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    let n_stations = journey.stations().len();
+    let roots = root.split_evenly((n_stations, 1));
+    let densities: Vec<Pdf> = (0..n_stations)
+        .map(|idx| journey.passengers_per_metre(idx, formation))
+        .collect();
+    let capacity = formation.capacity_per_metre();
+    let y_max = densities
+        .iter()
+        .flat_map(|pdf| pdf.densities())
+        .fold(capacity, f64::max)
+        * 1.1;
+    let x_range = journey.grid().plot_range();
+
+    for ((idx, r), station) in roots.iter().enumerate().zip(journey.stations())
+    {
+        r.titled(&station.station_name, (TITLE_FONT, 20_i32))?;
+        let mut chart = basic_chart!(r)
+            .margin_top(30_i32)
+            .build_cartesian_2d(x_range.clone(), 0.0..y_max)
+            .unwrap();
+
+        let mut mesh = chart.configure_mesh();
+        let mesh = mesh
+            .y_desc("passengers/m")
+            .axis_desc_style(("sans-serif", 20_i32).into_text_style(r))
+            .light_line_style(WHITE);
+        if idx == n_stations - 1 {
+            mesh.x_desc("xpos").draw()?;
+        } else {
+            mesh.draw()?;
+        }
+
+        for car in 0..formation.cars {
+            let (front, _) = formation.car_bounds(car);
+            chart.draw_series(LineSeries::new(
+                [(front, 0.0), (front, y_max)],
+                GRAY.mix(0.3),
+            ))?;
+        }
+        chart.draw_series(LineSeries::new(
+            [(x_range.start, capacity), (x_range.end, capacity)],
+            RED.stroke_width(2),
+        ))?;
+        chart.draw_series(LineSeries::new(
+            densities[idx].iter().copied(),
+            BLUE.stroke_width(2),
+        ))?;
+
+        let modifier = r.get_base_pixel().1;
+        plot_platform_bounds(&chart, r, modifier, 30)?;
+    }
+
+    root.present()?;
+    Ok(())
+}
+
+/// Plots the predicted minus the observed share of every observed car, with a
+/// line for every evaluated station
+pub fn plot_residuals(
+    filename: &str,
+    evaluations: &[StationEvaluation],
+    formation: &Formation,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_to_file!(
+        filename,
+        (1024, 768),
+        draw_residuals(evaluations, formation)
+    )
+}
+
+fn draw_residuals<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    evaluations: &[StationEvaluation],
+    formation: &Formation,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    let y_max = evaluations
+        .iter()
+        .flat_map(|evaluation| &evaluation.residuals)
+        .map(|(_, residual)| residual.abs())
+        .fold(0.01, f64::max)
+        * 1.1;
+    let x_range = 0.5..formation.cars as f64 + 0.5;
+
+    let mut chart = basic_chart!(&root)
+        .margin_top(30_i32)
+        .build_cartesian_2d(x_range.clone(), -y_max..y_max)
+        .unwrap();
+
+    chart
+        .configure_mesh()
+        .x_desc("car")
+        .y_desc("predicted - observed share")
+        .x_labels(formation.cars)
+        .x_label_formatter(&|x| format!("{:.0}", x))
+        .axis_desc_style(("sans-serif", 20_i32).into_text_style(&root))
+        .light_line_style(WHITE)
+        .draw()?;
+
+    chart.draw_series(LineSeries::new(
+        [(x_range.start, 0.0), (x_range.end, 0.0)],
+        black_stroke(),
+    ))?;
+    for (evaluation, color) in
+        evaluations.iter().zip(palette(evaluations.len()))
+    {
+        let points = evaluation
+            .residuals
+            .iter()
+            .map(|(car, residual)| (*car as f64, *residual));
+        chart
+            .draw_series(LineSeries::new(
+                points.clone(),
+                color.stroke_width(2),
+            ))?
+            .label(&evaluation.station)
+            .add_legend_icon(color);
+        chart.draw_series(
+            points.map(|point| Circle::new(point, 4, color.filled())),
+        )?;
+    }
+    add_legend!(chart, TITLE_FONT)?;
+
+    root.present()?;
+    Ok(())
+}
+
+/// Plots the front share after every station against the value of every
+/// parameter of a one-at-a-time sweep, one parameter per panel, with the value
+/// of the base stair model as a vertical line
+pub fn plot_sensitivity(
+    filename: &str,
+    runs: &[SensitivityRun],
+    base: &StairModel,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_to_file!(filename, (1536, 1152), draw_sensitivity(runs, base))
+}
+
+fn draw_sensitivity<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    runs: &[SensitivityRun],
+    base: &StairModel,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    // the same range in every panel, to compare the parameters
+    let shares = runs
+        .iter()
+        .flat_map(|run| run.metrics.iter().map(|m| m.front_share));
+    let y_min = shares.clone().fold(0.5, f64::min) - 0.02;
+    let y_max = shares.fold(0.5, f64::max) + 0.02;
+
+    let roots = root.split_evenly((3, 2));
+    for (idx, (parameter, r)) in PARAMETERS.iter().zip(&roots).enumerate() {
+        let sweep: Vec<&SensitivityRun> = runs
+            .iter()
+            .filter(|run| run.varied == Some(*parameter))
+            .collect();
+        let (start, end) = parameter.range();
+
+        r.titled(parameter.name(), ("sans-serif", 20_i32))?;
+        let mut chart = basic_chart!(r)
+            .margin_top(30_i32)
+            .build_cartesian_2d(start..end, y_min..y_max)
+            .unwrap();
+        chart
+            .configure_mesh()
+            .y_desc("front share")
+            .axis_desc_style(("sans-serif", 20_i32).into_text_style(r))
+            .light_line_style(WHITE)
+            .draw()?;
+
+        let n_stations = sweep.first().map_or(0, |run| run.metrics.len());
+        for (station, color) in (0..n_stations).zip(palette(n_stations)) {
+            let points = sweep.iter().map(|run| {
+                (
+                    parameter.get(&run.stair_model),
+                    run.metrics[station].front_share,
+                )
+            });
+            chart
+                .draw_series(LineSeries::new(points, color.stroke_width(2)))?
+                .label(&sweep[0].metrics[station].station)
+                .add_legend_icon(color);
+        }
+        let value = parameter.get(base);
+        chart.draw_series(LineSeries::new(
+            [(value, y_min), (value, y_max)],
+            BLACK.mix(0.5).stroke_width(2),
+        ))?;
+        if idx == 0 {
+            add_legend!(chart, TITLE_FONT)?;
+        }
+    }
+
+    root.present()?;
+    Ok(())
+}
+
+/// Plots how far the front share after a station moves from its value with the
+/// base stair model when every parameter is swept over its range, with the
+/// parameter with the largest swing at the top
+pub fn plot_tornado(
+    filename: &str,
+    runs: &[SensitivityRun],
+    base: &[StationMetrics],
+    station: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_to_file!(filename, (1024, 512), draw_tornado(runs, base, station))
+}
+
+fn draw_tornado<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    runs: &[SensitivityRun],
+    base: &[StationMetrics],
+    station: usize,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    let base_share = base[station].front_share;
+    // the lowest and highest change of the front share for every parameter
+    let mut swings: Vec<(Parameter, f64, f64)> = PARAMETERS
+        .iter()
+        .map(|parameter| {
+            let changes = runs
+                .iter()
+                .filter(|run| run.varied == Some(*parameter))
+                .map(|run| run.metrics[station].front_share - base_share);
+            let low = changes.clone().fold(0.0, f64::min);
+            let high = changes.fold(0.0, f64::max);
+            (*parameter, low, high)
+        })
+        .collect();
+    swings.sort_by(|(_, a_low, a_high), (_, b_low, b_high)| {
+        (a_high - a_low).total_cmp(&(b_high - b_low))
+    });
+    let x_max = swings
+        .iter()
+        .map(|(_, low, high)| low.abs().max(*high))
+        .fold(0.01, f64::max)
+        * 1.1;
+    let n = swings.len();
+
+    root.titled(&base[station].station, (TITLE_FONT, 20_i32))?;
+    let mut chart = basic_chart!(&root)
+        .margin_top(30_i32)
+        .y_label_area_size(160_i32)
+        .build_cartesian_2d(-x_max..x_max, 0.0..n as f64)
+        .unwrap();
+    let label = |y: &f64| {
+        let idx = y.floor() as usize;
+        match swings.get(idx) {
+            Some((parameter, ..)) if y.fract() == 0.5 => parameter.name(),
+            _ => "",
+        }
+        .to_string()
+    };
+    chart
+        .configure_mesh()
+        .x_desc(format!("change of front share from {:.3}", base_share))
+        .axis_desc_style(("sans-serif", 20_i32).into_text_style(&root))
+        .y_labels(2 * n + 1)
+        .y_label_formatter(&label)
+        .disable_y_mesh()
+        .light_line_style(WHITE)
+        .draw()?;
 
+    let color = palette(1)[0];
+    chart.draw_series(swings.iter().enumerate().map(
+        |(idx, (_, low, high))| {
+            let y = idx as f64;
+            Rectangle::new([(*low, y + 0.2), (*high, y + 0.8)], color.filled())
+        },
+    ))?;
+    chart.draw_series(LineSeries::new(
+        [(0.0, 0.0), (0.0, n as f64)],
+        black_stroke(),
+    ))?;
+
+    root.present()?;
     Ok(())
 }
@@ -4,13 +4,39 @@ use plotters::coord::types::RangedCoordf64;
 use plotters::coord::Shift;
 use plotters::prelude::*;
 
-pub const SUBSCRIPTS: &[&str] = &["₁", "₂", "₃", "₄"];
+/// n written with Unicode subscript digits
+pub fn subscript(n: usize) -> String {
+    const DIGITS: [char; 10] =
+        ['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'];
+    n.to_string()
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|d| DIGITS[d as usize])
+        .collect()
+}
+
+pub type Chart<'a, DB> =
+    ChartContext<'a, DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>;
+
+/// whether to draw the plot as an SVG rather than a bitmap
+pub fn is_svg(filename: &str) -> bool {
+    std::path::Path::new(filename)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"))
+}
 
-pub type Chart<'a, 'b> = ChartContext<
-    'a,
-    BitMapBackend<'b>,
-    Cartesian2d<RangedCoordf64, RangedCoordf64>,
->;
+// Calls a function that draws on a drawing area with a backend chosen by the
+// extension of the file. A macro because the function is generic over the
+// backend
+macro_rules! draw_to_file {
+    ($filename: expr, $size: expr, $draw: ident($($arg: expr),* $(,)?)) => {{
+        if is_svg($filename) {
+            $draw(SVGBackend::new($filename, $size).into_drawing_area(), $($arg),*)
+        } else {
+            $draw(BitMapBackend::new($filename, $size).into_drawing_area(), $($arg),*)
+        }
+    }};
+}
 
 // This is a macro to avoid lifetime issues from CT due to root
 macro_rules! basic_chart {
@@ -27,10 +53,10 @@ macro_rules! basic_chart {
 
 // macro used to avoid lifetime errors
 macro_rules! chart_with_mesh {
-    ($root: expr, $y_range: expr) => {{
+    ($root: expr, $x_range: expr, $y_range: expr) => {{
         let mut chart = basic_chart!($root)
             .margin_top(30_i32)
-            .build_cartesian_2d(-10.0..110.0_f64, $y_range)
+            .build_cartesian_2d($x_range, $y_range)
             .unwrap();
 
         chart
@@ -44,10 +70,13 @@ macro_rules! chart_with_mesh {
 }
 
 macro_rules! add_legend {
-    ($chart: expr, $font: expr) => {{
+    ($chart: expr, $font: expr) => {
+        add_legend!($chart, $font, SeriesLabelPosition::UpperRight)
+    };
+    ($chart: expr, $font: expr, $position: expr) => {{
         $chart
             .configure_series_labels()
-            .position(SeriesLabelPosition::UpperRight)
+            .position($position)
             .background_style(WHITE.filled())
             .border_style(&BLACK.mix(0.5))
             .legend_area_size(22_i32)
@@ -56,35 +85,44 @@ macro_rules! add_legend {
     }};
 }
 
-pub fn plot_platform_bounds(
-    chart: &Chart,
-    root: &DrawingArea<BitMapBackend, Shift>,
+pub fn plot_platform_bounds<DB: DrawingBackend>(
+    chart: &Chart<DB>,
+    root: &DrawingArea<DB, Shift>,
     modifier: i32,
     top_y: i32,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
     plot_vline(root, chart, 0.0, modifier, top_y, black_stroke())?;
     plot_vline(root, chart, 100.0, modifier, top_y, black_stroke())?;
     Ok(())
 }
 
-pub fn plot_stairs(
-    root: &DrawingArea<BitMapBackend, Shift>,
-    chart: &Chart,
+pub fn plot_stairs<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    chart: &Chart<DB>,
     stair: f64,
     modifier: i32,
     top_y: i32,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
     plot_vline(root, chart, stair, modifier, top_y, lighter_stroke())
 }
 
-fn plot_vline(
-    root: &DrawingArea<BitMapBackend, Shift>,
-    chart: &Chart,
+fn plot_vline<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    chart: &Chart<DB>,
     stair: f64,
     modifier: i32,
     top_y: i32,
     stroke: ShapeStyle,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
     let drawing_area = chart.plotting_area();
     let mapped = drawing_area.map_coordinate(&(stair, 0.0));
     let p: PathElement<(i32, i32)> = PathElement::new(
@@ -99,11 +137,10 @@ pub trait Ext {
     fn add_legend_icon(&mut self, color: RGBColor);
 }
 
-impl Ext for SeriesAnno<'_, BitMapBackend<'_>> {
+impl<DB: DrawingBackend> Ext for SeriesAnno<'_, DB> {
     fn add_legend_icon(&mut self, color: RGBColor) {
         self.legend(move |(x, y)| {
             Rectangle::new([(x, y - 6), (x + 12, y + 6)], color.filled())
         });
     }
 }
-
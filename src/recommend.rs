@@ -0,0 +1,146 @@
+use crate::error::{Error, Result};
+use crate::types::*;
+use crate::Journey;
+
+/// the average of the passengers per metre within half the distance between
+/// doors of the door
+fn crowding_at_door(
+    passengers_per_metre: &Pdf,
+    door: &Door,
+    formation: &Formation,
+) -> f64 {
+    let n_doors = formation.door_positions.len().max(1);
+    let half_width =
+        formation.to_percent(formation.car_length / n_doors as f64 / 2.0);
+    let from = door.position - half_width;
+    let to = door.position + half_width;
+    // mass_between integrates over proportions of the platform
+    passengers_per_metre.mass_between(from, to) / ((to - from) / 100.0)
+}
+
+/// Ranks the doors of the train by the expected crowding around them after
+/// the station, and returns the `n` least crowded doors to wait at. If a
+/// destination is given, the crowding is averaged over the ride from the
+/// station to the destination. Doors outside the sampled positions are
+/// skipped, because their crowding is unknown
+pub fn recommend_doors(
+    journey: &Journey,
+    station: usize,
+    destination: Option<usize>,
+    formation: &Formation,
+    n: usize,
+) -> Result<Vec<DoorRecommendation>> {
+    let last = match destination {
+        Some(destination) if destination <= station => {
+            let name =
+                |idx: usize| journey.stations()[idx].station_name.clone();
+            return Err(Error::DestinationNotAfter {
+                station: name(station),
+                destination: name(destination),
+            });
+        }
+        // the rider is in the train after every station before the
+        // destination
+        Some(destination) => destination - 1,
+        None => station,
+    };
+    let ride: Vec<Pdf> = (station..=last)
+        .map(|idx| journey.passengers_per_metre(idx, formation))
+        .collect();
+    let grid = journey.grid();
+
+    let mut recommendations: Vec<DoorRecommendation> = formation
+        .doors()
+        .into_iter()
+        .filter(|door| (grid.start()..=grid.end()).contains(&door.position))
+        .map(|door| {
+            let passengers_per_metre = ride
+                .iter()
+                .map(|pdf| crowding_at_door(pdf, &door, formation))
+                .sum::<f64>()
+                / ride.len() as f64;
+            DoorRecommendation {
+                door,
+                passengers_per_metre,
+                load_factor: passengers_per_metre
+                    / formation.capacity_per_metre(),
+            }
+        })
+        .collect();
+    recommendations.sort_by(|a, b| {
+        a.passengers_per_metre.total_cmp(&b.passengers_per_metre)
+    });
+    recommendations.truncate(n);
+    Ok(recommendations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::read_dataset;
+    use crate::journey::test_journey;
+
+    #[test]
+    fn ranks_the_least_crowded_doors_first() {
+        let journey = test_journey(StairModel::default());
+        let formation = Formation::default();
+        let doors = formation.doors().len();
+        let all =
+            recommend_doors(&journey, 1, None, &formation, doors).unwrap();
+        assert_eq!(all.len(), doors);
+        for pair in all.windows(2) {
+            assert!(
+                pair[0].passengers_per_metre <= pair[1].passengers_per_metre
+            );
+        }
+
+        let best =
+            recommend_doors(&journey, 1, Some(3), &formation, 3).unwrap();
+        assert_eq!(best.len(), 3);
+        for pair in best.windows(2) {
+            assert!(
+                pair[0].passengers_per_metre <= pair[1].passengers_per_metre
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_destinations_not_after_the_station() {
+        let journey = test_journey(StairModel::default());
+        for destination in [0, 1] {
+            match recommend_doors(
+                &journey,
+                1,
+                Some(destination),
+                &Formation::default(),
+                3,
+            ) {
+                Err(Error::DestinationNotAfter { station, .. }) => {
+                    assert_eq!(station, "神田")
+                }
+                result => panic!("{:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn skips_doors_outside_the_grid() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let journey = Journey::new(
+            &read_dataset(root).unwrap(),
+            "中央本線",
+            &["東京", "神田"],
+            Direction::Down,
+            StairModel::default(),
+            None,
+            SampleGrid::new(0.0, 50.0, 51).unwrap(),
+        )
+        .unwrap();
+        let formation = Formation::default();
+        let doors = formation.doors().len();
+        let recommendations =
+            recommend_doors(&journey, 0, None, &formation, doors).unwrap();
+        assert_eq!(recommendations.len(), doors / 2);
+        assert!(recommendations.iter().all(|r| r.door.position <= 50.0));
+    }
+}
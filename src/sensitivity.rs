@@ -0,0 +1,305 @@
+use crate::error::Result;
+use crate::types::*;
+use crate::Journey;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+
+/// A parameter of the stair model to vary
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parameter {
+    FarConcentration,
+    CloseConcentration,
+    PropNormalFar,
+    PropNormalClose,
+    PropUniform,
+}
+
+pub const PARAMETERS: [Parameter; 5] = [
+    Parameter::FarConcentration,
+    Parameter::CloseConcentration,
+    Parameter::PropNormalFar,
+    Parameter::PropNormalClose,
+    Parameter::PropUniform,
+];
+
+impl Parameter {
+    /// the name of the field of the stair model
+    pub fn name(self) -> &'static str {
+        match self {
+            Parameter::FarConcentration => "far_concentration",
+            Parameter::CloseConcentration => "close_concentration",
+            Parameter::PropNormalFar => "prop_normal_far",
+            Parameter::PropNormalClose => "prop_normal_close",
+            Parameter::PropUniform => "prop_uniform",
+        }
+    }
+
+    /// the values swept, which the concentrations must be at least 2 and
+    /// the proportions between 0 and 1 for
+    pub fn range(self) -> (f64, f64) {
+        match self {
+            Parameter::FarConcentration => (2.0, 20.0),
+            Parameter::CloseConcentration => (2.0, 50.0),
+            _ => (0.0, 1.0),
+        }
+    }
+
+    pub fn get(self, stair_model: &StairModel) -> f64 {
+        match self {
+            Parameter::FarConcentration => stair_model.far_concentration,
+            Parameter::CloseConcentration => stair_model.close_concentration,
+            Parameter::PropNormalFar => stair_model.prop_normal_far,
+            Parameter::PropNormalClose => stair_model.prop_normal_close,
+            Parameter::PropUniform => stair_model.prop_uniform,
+        }
+    }
+
+    /// the stair model with the parameter set to the value. Setting a
+    /// proportion scales the other two so that they still sum to 1
+    pub fn set(self, stair_model: &StairModel, value: f64) -> StairModel {
+        let mut stair_model = stair_model.clone();
+        let idx = match self {
+            Parameter::FarConcentration => {
+                stair_model.far_concentration = value;
+                return stair_model;
+            }
+            Parameter::CloseConcentration => {
+                stair_model.close_concentration = value;
+                return stair_model;
+            }
+            Parameter::PropNormalFar => 0,
+            Parameter::PropNormalClose => 1,
+            Parameter::PropUniform => 2,
+        };
+        let mut props = [
+            stair_model.prop_normal_far,
+            stair_model.prop_normal_close,
+            stair_model.prop_uniform,
+        ];
+        let others = props.iter().sum::<f64>() - props[idx];
+        for (i, p) in props.iter_mut().enumerate() {
+            *p = if i == idx {
+                value
+            } else if others > 0.0 {
+                *p / others * (1.0 - value)
+            } else {
+                (1.0 - value) / 2.0
+            };
+        }
+        [
+            stair_model.prop_normal_far,
+            stair_model.prop_normal_close,
+            stair_model.prop_uniform,
+        ] = props;
+        stair_model
+    }
+}
+
+/// Summary of the pdf after a station
+#[derive(Clone, Debug, Serialize)]
+pub struct StationMetrics {
+    pub station: String,
+    /// the proportion of the passengers in the front half of the platform
+    pub front_share: f64,
+    pub back_share: f64,
+    /// the largest density of the pdf
+    pub peak_density: f64,
+    /// the share of every car of the formation, from the front
+    pub car_shares: Vec<f64>,
+}
+
+/// The metrics of the journey recomputed with a stair model
+#[derive(Clone, Debug, Serialize)]
+pub struct SensitivityRun {
+    pub stair_model: StairModel,
+    /// the parameter varied from the stair model of the journey, for a
+    /// one-at-a-time sweep
+    pub varied: Option<Parameter>,
+    pub metrics: Vec<StationMetrics>,
+}
+
+/// the metrics of every station of the journey
+pub fn station_metrics(
+    journey: &Journey,
+    formation: &Formation,
+) -> Vec<StationMetrics> {
+    journey
+        .stations()
+        .iter()
+        .zip(journey.pdfs())
+        .enumerate()
+        .map(|(idx, (station, pdf))| {
+            let front = pdf.mass_between(0.0, 50.0);
+            let back = pdf.mass_between(50.0, 100.0);
+            let front_share = front / (front + back).max(f64::EPSILON);
+            StationMetrics {
+                station: station.station_name.clone(),
+                front_share,
+                back_share: 1.0 - front_share,
+                peak_density: pdf.densities().fold(0.0, f64::max),
+                car_shares: journey
+                    .car_loads(idx, formation)
+                    .iter()
+                    .map(|car| car.share)
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+fn run(
+    journey: &Journey,
+    formation: &Formation,
+    stair_model: StairModel,
+    varied: Option<Parameter>,
+) -> Result<SensitivityRun> {
+    let journey = journey.clone().with_stair_model(stair_model.clone())?;
+    Ok(SensitivityRun {
+        metrics: station_metrics(&journey, formation),
+        stair_model,
+        varied,
+    })
+}
+
+/// Sweeps every parameter over its range in `steps` evenly spaced values,
+/// keeping the others at the stair model of the journey
+pub fn one_at_a_time(
+    journey: &Journey,
+    formation: &Formation,
+    steps: usize,
+) -> Result<Vec<SensitivityRun>> {
+    let base = journey.stair_model();
+    PARAMETERS
+        .iter()
+        .flat_map(|parameter| {
+            let (start, end) = parameter.range();
+            (0..steps).map(move |step| {
+                let t = step as f64 / (steps - 1).max(1) as f64;
+                (*parameter, start + t * (end - start))
+            })
+        })
+        .map(|(parameter, value)| {
+            let stair_model = parameter.set(base, value);
+            run(journey, formation, stair_model, Some(parameter))
+        })
+        .collect()
+}
+
+/// the samples of every parameter in the order of PARAMETERS, with one
+/// sample in each of `samples` equal strata of its range in a random order
+fn hypercube_columns(samples: usize, seed: u64) -> Vec<Vec<f64>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    PARAMETERS
+        .iter()
+        .map(|parameter| {
+            let (start, end) = parameter.range();
+            let mut strata: Vec<usize> = (0..samples).collect();
+            strata.shuffle(&mut rng);
+            strata
+                .into_iter()
+                .map(|stratum| {
+                    let t =
+                        (stratum as f64 + rng.gen::<f64>()) / samples as f64;
+                    start + t * (end - start)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Varies every parameter at once with a Latin hypercube of `samples` stair
+/// models. The concentrations have one sample in each of `samples` equal
+/// strata of their ranges. The proportions are sampled the same way as
+/// weights and then normalised to sum to 1, so the proportions themselves
+/// are not stratified
+pub fn latin_hypercube(
+    journey: &Journey,
+    formation: &Formation,
+    samples: usize,
+    seed: u64,
+) -> Result<Vec<SensitivityRun>> {
+    let columns = hypercube_columns(samples, seed);
+    (0..samples)
+        .map(|sample| {
+            // the columns are in the order of PARAMETERS
+            let value = |i: usize| columns[i][sample];
+            let props = [value(2), value(3), value(4)];
+            let total: f64 = props.iter().sum::<f64>().max(f64::EPSILON);
+            let stair_model = StairModel {
+                far_concentration: value(0),
+                close_concentration: value(1),
+                prop_normal_far: props[0] / total,
+                prop_normal_close: props[1] / total,
+                prop_uniform: props[2] / total,
+                ..journey.stair_model().clone()
+            };
+            run(journey, formation, stair_model, None)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(stair_model: &StairModel) -> [f64; 3] {
+        [
+            stair_model.prop_normal_far,
+            stair_model.prop_normal_close,
+            stair_model.prop_uniform,
+        ]
+    }
+
+    #[test]
+    fn setting_a_proportion_keeps_the_sum() {
+        let proportions = [
+            Parameter::PropNormalFar,
+            Parameter::PropNormalClose,
+            Parameter::PropUniform,
+        ];
+        for parameter in proportions {
+            for value in [0.0, 0.3, 1.0] {
+                let stair_model = parameter.set(&StairModel::default(), value);
+                let sum: f64 = props(&stair_model).iter().sum();
+                assert!((sum - 1.0).abs() < 1e-12, "{:?}", stair_model);
+                assert_eq!(parameter.get(&stair_model), value);
+                assert!(stair_model.validate().is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn setting_a_proportion_when_the_others_are_0() {
+        let stair_model =
+            Parameter::PropUniform.set(&StairModel::default(), 1.0);
+        assert_eq!(props(&stair_model), [0.0, 0.0, 1.0]);
+        // the others share the rest evenly
+        let stair_model = Parameter::PropUniform.set(&stair_model, 0.4);
+        assert_eq!(props(&stair_model), [0.3, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn hypercube_has_one_sample_per_stratum() {
+        let samples = 20;
+        let columns = hypercube_columns(samples, 3);
+        for (parameter, column) in PARAMETERS.iter().zip(&columns) {
+            let (start, end) = parameter.range();
+            let mut strata: Vec<usize> = column
+                .iter()
+                .map(|value| {
+                    ((value - start) / (end - start) * samples as f64) as usize
+                })
+                .collect();
+            strata.sort();
+            assert_eq!(
+                strata,
+                (0..samples).collect::<Vec<_>>(),
+                "{:?}",
+                parameter
+            );
+        }
+    }
+}
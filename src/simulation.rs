@@ -0,0 +1,195 @@
+use crate::data::sample_stair_position;
+use crate::types::*;
+use crate::Journey;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Parameters of the Monte Carlo simulation
+#[derive(Clone, Debug)]
+pub struct SimulationConfig {
+    /// the passengers boarding at the first station. The passengers at the
+    /// other stations are scaled from it with p^b and p^a
+    pub passengers: usize,
+    /// the same seed always gives the same passengers
+    pub seed: u64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            passengers: 10_000,
+            seed: 0,
+        }
+    }
+}
+
+/// Simulates discrete passengers through the journey, with the same model as
+/// the pdfs. Boarders come from a stair of the station (or of their
+/// destination) and walk to a position sampled from S of the stair. Alighters
+/// are drawn from the passengers in the train, preferring those close to the
+/// stairs. Returns the positions of the passengers in the train after every
+/// station, as percentages of the platform
+pub fn simulate(journey: &Journey, config: &SimulationConfig) -> Vec<Vec<f64>> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let stair_model = journey.stair_model();
+    let w = stair_model.alighting_attraction;
+    let mut onboard: Vec<f64> = vec![];
+    let mut positions = Vec::with_capacity(journey.stations().len());
+
+    for (i, station) in journey.stations().iter().enumerate() {
+        let alighters = (journey.alighter_props()[i] * onboard.len() as f64)
+            .round() as usize;
+        if alighters > 0 {
+            let stair_pdf = journey.stair_density(i);
+            onboard = remove_weighted(onboard, alighters, &mut rng, |x| {
+                (1.0 - w) + w * stair_pdf.density_at(x)
+            });
+        }
+
+        let boarder_prop = journey.boarder_props()[i];
+        let boarders = if boarder_prop >= 1.0 || onboard.is_empty() {
+            onboard.clear();
+            config.passengers
+        } else {
+            (boarder_prop / (1.0 - boarder_prop) * onboard.len() as f64).round()
+                as usize
+        };
+
+        let flows = &journey.destination_flows()[i];
+        let destinations = WeightedIndex::new(flows).ok();
+        let blend = match destinations {
+            Some(_) => stair_model.destination_blend,
+            None => 0.0,
+        };
+        for _ in 0..boarders {
+            let stairs = match &destinations {
+                Some(destinations) if rng.gen::<f64>() < blend => {
+                    &journey.stations()[destinations.sample(&mut rng)]
+                }
+                _ => station,
+            };
+            let stair = WeightedIndex::new(&stairs.stair_weights)
+                .map(|stair| stairs.stair_locations[stair.sample(&mut rng)])
+                .unwrap_or(50.0);
+            onboard.push(sample_stair_position(stair, stair_model, &mut rng));
+        }
+        positions.push(onboard.clone());
+    }
+    positions
+}
+
+/// removes n of the positions at random, in proportion to their weights
+fn remove_weighted<R: Rng>(
+    positions: Vec<f64>,
+    n: usize,
+    rng: &mut R,
+    weight: impl Fn(f64) -> f64,
+) -> Vec<f64> {
+    // weighted sampling without replacement by Efraimidis and Spirakis: the
+    // positions with the n largest u^(1/weight) are removed
+    let mut keyed: Vec<(f64, f64)> = positions
+        .into_iter()
+        .map(|x| {
+            let u: f64 = rng.gen();
+            (u.powf(1.0 / weight(x).max(f64::EPSILON)), x)
+        })
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    keyed.truncate(keyed.len().saturating_sub(n));
+    keyed.into_iter().map(|(_, x)| x).collect()
+}
+
+/// the gaussian kernel density estimate of the positions at the sampled
+/// positions of the grid, with Silverman's rule of thumb for the bandwidth.
+/// The kernels are reflected at the ends of the platform, where the
+/// passengers cannot go past
+pub fn kde(positions: &[f64], grid: &SampleGrid) -> Pdf {
+    let n = positions.len() as f64;
+    if positions.is_empty() {
+        return Pdf(grid.xs().into_iter().map(|x| (x, 0.0)).collect());
+    }
+    let mean = positions.iter().sum::<f64>() / n;
+    let sd =
+        (positions.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+    let bandwidth = (1.06 * sd * n.powf(-0.2)).max(f64::EPSILON);
+    let kernel =
+        |u: f64| (-0.5 * u * u).exp() / (2.0 * std::f64::consts::PI).sqrt();
+    Pdf(grid
+        .xs()
+        .into_iter()
+        .map(|x| {
+            let density: f64 = positions
+                .iter()
+                .map(|p| {
+                    kernel((x - p) / bandwidth)
+                        + kernel((x + p) / bandwidth)
+                        + kernel((x - (200.0 - p)) / bandwidth)
+                })
+                .sum::<f64>()
+                / (n * bandwidth);
+            // per percent to per proportion of the platform, and nobody is
+            // outside the platform
+            let inside = (0.0..=100.0).contains(&x);
+            (x, if inside { density * 100.0 } else { 0.0 })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journey::test_journey;
+
+    fn config(seed: u64) -> SimulationConfig {
+        SimulationConfig {
+            passengers: 500,
+            seed,
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_passengers() {
+        let journey = test_journey(StairModel::default());
+        assert_eq!(
+            simulate(&journey, &config(7)),
+            simulate(&journey, &config(7))
+        );
+    }
+
+    #[test]
+    fn different_seeds_give_different_passengers() {
+        let journey = test_journey(StairModel::default());
+        assert_ne!(
+            simulate(&journey, &config(7)),
+            simulate(&journey, &config(8))
+        );
+    }
+
+    #[test]
+    fn agrees_with_the_pdfs() {
+        let journey = test_journey(StairModel::default());
+        let config = SimulationConfig {
+            passengers: 5_000,
+            seed: 1,
+        };
+        for (i, mut positions) in
+            simulate(&journey, &config).into_iter().enumerate()
+        {
+            positions.sort_by(f64::total_cmp);
+            let cdf = journey.cdf_after(i);
+            for p in [0.1, 0.25, 0.5, 0.75, 0.9] {
+                let k = ((positions.len() - 1) as f64 * p).round() as usize;
+                let (simulated, expected) = (positions[k], cdf.quantile(p));
+                assert!(
+                    (simulated - expected).abs() < 2.0,
+                    "quantile {} after station {} is {} but {} in the pdf",
+                    p,
+                    i,
+                    simulated,
+                    expected
+                );
+            }
+        }
+    }
+}
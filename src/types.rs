@@ -1,11 +1,34 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
+/// The link load data of every line and the directory of the station maps,
+/// read once and shared by every journey
+#[derive(Clone, Debug)]
+pub struct Dataset {
+    /// where the link load data was read from, for errors
+    pub link_load_path: String,
+    /// the name of every line and the rows of its stations, in the down
+    /// direction
+    pub lines: Vec<(String, Vec<csv::StringRecord>)>,
+    pub maps_dir: PathBuf,
+}
+
+impl Dataset {
+    /// the path of the SVG map of a station
+    pub fn map_path(&self, station: &str) -> PathBuf {
+        self.maps_dir.join(format!("{}.svg", station))
+    }
+}
+
+/// A row of origin-destination data. The station codes are the station names
+/// used in the map file names
 #[derive(Clone, Debug, Deserialize)]
 pub struct OdRow {
     pub index: i32,
     pub from_station_code: String,
     pub to_station_code: String,
     pub count: i64,
+    #[serde(default)]
     pub stations: Vec<String>,
 }
 
@@ -13,5 +36,665 @@ pub struct OdRow {
 pub struct StationStairs {
     pub station_name: String,
     pub stair_locations: Vec<f64>,
+    /// proportion of boarders coming from each stair; sums to 1
+    pub stair_weights: Vec<f64>,
+}
+
+/// The direction of travel. The link load data lists stations in the down
+/// direction, and the front of the train is at the end of the platform
+/// closer to the start of the map for the down direction
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl Direction {
+    /// the columns for (boardings, alightings, passing) in the link load data
+    pub fn columns(&self) -> (usize, usize, usize) {
+        match self {
+            Direction::Down => (1, 2, 3),
+            Direction::Up => (4, 5, 6),
+        }
+    }
+}
+
+impl std::str::FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "up" | "上り" => Ok(Direction::Up),
+            "down" | "下り" => Ok(Direction::Down),
+            _ => Err(format!("direction must be up or down, got {}", s)),
+        }
+    }
+}
+
+/// Passenger counts of a station from the link load data
+#[derive(Clone, Debug)]
+pub struct StationLoad {
+    pub boardings: i64,
+    pub alightings: i64,
+    /// passengers in the train after leaving the station
+    pub onboard: i64,
+}
+
+impl StationLoad {
+    /// passengers in the train when arriving at the station
+    pub fn onboard_before(&self) -> i64 {
+        self.onboard - self.boardings + self.alightings
+    }
+}
+
+/// A probability density function sampled along the train, as (xpos, density)
+/// where xpos is the percentage of the platform from the front of the train
+#[derive(Clone, Debug)]
+pub struct Pdf(pub Vec<(f64, f64)>);
+
+impl Pdf {
+    pub fn xs(&self) -> impl Iterator<Item = f64> + '_ {
+        self.0.iter().map(|(x, _)| *x)
+    }
+
+    pub fn densities(&self) -> impl Iterator<Item = f64> + '_ {
+        self.0.iter().map(|(_, y)| *y)
+    }
+
+    /// the cdf, integrated with the trapezoidal rule and normalised to end
+    /// at 1 so that it is the proportion of the passengers in the train
+    pub fn cdf(&self) -> Cdf {
+        let mut total = 0.0;
+        let mut cumulative = Vec::with_capacity(self.0.len());
+        for (i, (x, y)) in self.0.iter().enumerate() {
+            if i > 0 {
+                let (prev_x, prev_y) = self.0[i - 1];
+                total += (x - prev_x) * (y + prev_y) / 2.0;
+            }
+            cumulative.push((*x, total));
+        }
+        if total > 0.0 {
+            for (_, y) in cumulative.iter_mut() {
+                *y /= total;
+            }
+        }
+        Cdf(cumulative)
+    }
+
+    /// the density at x, linearly interpolated between the samples and 0
+    /// outside of them
+    pub fn density_at(&self, x: f64) -> f64 {
+        match self.0.iter().position(|(x2, _)| *x2 >= x) {
+            Some(0) if self.0[0].0 > x => 0.0,
+            Some(0) => self.0[0].1,
+            Some(i) => {
+                let (x1, y1) = self.0[i - 1];
+                let (x2, y2) = self.0[i];
+                y1 + (y2 - y1) * (x - x1) / (x2 - x1)
+            }
+            None => 0.0,
+        }
+    }
+
+    /// the proportion of passengers between the positions `from` and `to`,
+    /// integrated with the trapezoidal rule. There are no passengers outside
+    /// the sampled positions
+    pub fn mass_between(&self, from: f64, to: f64) -> f64 {
+        let (first, last) = match (self.0.first(), self.0.last()) {
+            (Some((first, _)), Some((last, _))) => (*first, *last),
+            _ => return 0.0,
+        };
+        let from = from.max(first);
+        let to = to.min(last);
+        if from >= to {
+            return 0.0;
+        }
+        let mut points = vec![(from, self.density_at(from))];
+        points.extend(self.0.iter().filter(|(x, _)| *x > from && *x < to));
+        points.push((to, self.density_at(to)));
+        // the xs are percentages but the densities are per proportion of
+        // the platform
+        points
+            .windows(2)
+            .map(|w| (w[1].0 - w[0].0) * (w[0].1 + w[1].1) / 2.0)
+            .sum::<f64>()
+            / 100.0
+    }
+}
+
+/// A cumulative distribution function sampled along the train, as
+/// (xpos, proportion of passengers in front of xpos)
+#[derive(Clone, Debug)]
+pub struct Cdf(pub Vec<(f64, f64)>);
+
+impl Cdf {
+    /// the position with the proportion p of the passengers in front of it,
+    /// linearly interpolated between the samples
+    pub fn quantile(&self, p: f64) -> f64 {
+        match self.0.iter().position(|(_, y)| *y >= p) {
+            Some(0) => self.0[0].0,
+            Some(i) => {
+                let (x1, y1) = self.0[i - 1];
+                let (x2, y2) = self.0[i];
+                x1 + (x2 - x1) * (p - y1) / (y2 - y1)
+            }
+            None => self.0.last().map_or(f64::NAN, |(x, _)| *x),
+        }
+    }
+}
+
+impl std::ops::Deref for Cdf {
+    type Target = [(f64, f64)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::Deref for Pdf {
+    type Target = [(f64, f64)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// The capacity of the train that the densities are dispersed by
+#[derive(Clone, Debug)]
+pub struct Dispersion {
+    /// the capacity of every station in the units of the pdfs
+    pub capacities: Vec<f64>,
+    /// the front and back of the train, as percentages of the platform
+    pub train: (f64, f64),
+}
+
+/// The densities that the pdf of the passengers in the train after a station
+/// is made from
+#[derive(Clone, Debug)]
+pub struct StationDensities {
+    /// b, the passengers boarding the train at the station
+    pub boarding_pdf: Pdf,
+    /// r, the passengers that stayed in the train after some of them alighted.
+    /// Zero for the first station
+    pub remaining_pdf: Pdf,
+    /// E, the passengers close to the stairs of the station
+    pub stair_pdf: Pdf,
+    /// the components of S for every stair, weighted by its traffic
+    pub stair_components: Vec<Vec<(f64, StairComponents)>>,
+    /// m before it was dispersed by the capacity of the train, the same as m
+    /// if it was not dispersed
+    pub mixture_pdf: Pdf,
+}
+
+/// The positions along the platform that the pdfs are sampled at, as the
+/// percentage of the platform from the front of the train. The domain can go
+/// beyond 0 and 100 to include stairs outside the train
+#[derive(Clone, Debug, Serialize)]
+pub struct SampleGrid {
+    start: f64,
+    end: f64,
+    resolution: usize,
+}
+
+impl Default for SampleGrid {
+    fn default() -> Self {
+        Self {
+            start: 0.0,
+            end: 100.0,
+            resolution: 101,
+        }
+    }
 }
 
+impl SampleGrid {
+    /// samples `resolution` evenly spaced points from start to end inclusive
+    pub fn new(
+        start: f64,
+        end: f64,
+        resolution: usize,
+    ) -> crate::error::Result<Self> {
+        // also rejects NaN
+        let is_valid = start < end && resolution >= 2;
+        if !is_valid {
+            return Err(crate::error::Error::SampleGrid {
+                start,
+                end,
+                resolution,
+            });
+        }
+        Ok(Self {
+            start,
+            end,
+            resolution,
+        })
+    }
+
+    pub fn start(&self) -> f64 {
+        self.start
+    }
+
+    pub fn end(&self) -> f64 {
+        self.end
+    }
+
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    pub fn xs(&self) -> Vec<f64> {
+        let step = (self.end - self.start) / (self.resolution - 1) as f64;
+        (0..self.resolution)
+            .map(|i| self.start + step * i as f64)
+            .collect()
+    }
+
+    /// the x range of the plots, with some space around the platform
+    pub fn plot_range(&self) -> std::ops::Range<f64> {
+        (self.start.min(0.0) - 10.0)..(self.end.max(100.0) + 10.0)
+    }
+}
+
+/// The three components of S for a single stair: (beta_far, beta_close, uniform)
+pub type StairComponents = (f64, f64, f64);
+
+/// Parameters of S, the mixture distribution of boarders coming from a stair.
+/// Missing fields in a config file fall back to the defaults
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StairModel {
+    /// p_f
+    pub prop_normal_far: f64,
+    /// p_c
+    pub prop_normal_close: f64,
+    /// p_u
+    pub prop_uniform: f64,
+    pub far_concentration: f64,
+    pub close_concentration: f64,
+    /// how much alighting passengers prefer to be close to the stairs, from
+    /// 0 (every passenger is equally likely to alight) to 1
+    pub alighting_attraction: f64,
+    /// proportion of boarders waiting near the stairs of their destination
+    /// instead of the stairs they entered from, from 0 to 1
+    pub destination_blend: f64,
+}
+
+impl Default for StairModel {
+    fn default() -> Self {
+        Self {
+            prop_normal_far: 0.6,
+            prop_normal_close: 0.3,
+            prop_uniform: 0.1,
+            far_concentration: 7.,
+            close_concentration: 20.,
+            alighting_attraction: 0.5,
+            destination_blend: 0.3,
+        }
+    }
+}
+
+fn invalid_stair_model(message: String) -> crate::error::Error {
+    crate::error::Error::StairModel {
+        path: None,
+        message,
+    }
+}
+
+impl StairModel {
+    /// Every parameter must be finite. The proportions must be non-negative
+    /// and sum to 1, the concentrations must be at least 2 for the beta
+    /// distribution to have a mode, and the alighting attraction and
+    /// destination blend must be between 0 and 1
+    pub fn validate(&self) -> crate::error::Result<()> {
+        let params = [
+            ("prop_normal_far", self.prop_normal_far),
+            ("prop_normal_close", self.prop_normal_close),
+            ("prop_uniform", self.prop_uniform),
+            ("far_concentration", self.far_concentration),
+            ("close_concentration", self.close_concentration),
+            ("alighting_attraction", self.alighting_attraction),
+            ("destination_blend", self.destination_blend),
+        ];
+        if let Some((name, value)) = params.iter().find(|(_, v)| !v.is_finite())
+        {
+            return Err(invalid_stair_model(format!(
+                "{} must be a finite number, got {}",
+                name, value
+            )));
+        }
+        let props = [
+            self.prop_normal_far,
+            self.prop_normal_close,
+            self.prop_uniform,
+        ];
+        if props.iter().any(|p| *p < 0.0) {
+            return Err(invalid_stair_model(format!(
+                "stair model proportions must be >= 0, got {:?}",
+                props
+            )));
+        }
+        let sum: f64 = props.iter().sum();
+        if (sum - 1.0).abs() > 1e-6 {
+            return Err(invalid_stair_model(format!(
+                "stair model proportions must sum to 1, got {}",
+                sum
+            )));
+        }
+        for concentration in [self.far_concentration, self.close_concentration]
+        {
+            if !(2.0..).contains(&concentration) {
+                return Err(invalid_stair_model(format!(
+                    "stair model concentrations must be >= 2, got {}",
+                    concentration
+                )));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.alighting_attraction) {
+            return Err(invalid_stair_model(format!(
+                "alighting attraction must be between 0 and 1, got {}",
+                self.alighting_attraction
+            )));
+        }
+        if !(0.0..=1.0).contains(&self.destination_blend) {
+            return Err(invalid_stair_model(format!(
+                "destination blend must be between 0 and 1, got {}",
+                self.destination_blend
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The cars of the train and where the train stops along the platform.
+/// Lengths are in metres. The front of the platform is the bound the front
+/// of the train stops at, which is the "start" guide for the down direction.
+/// Missing fields in a config file fall back to the defaults
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Formation {
+    pub cars: usize,
+    pub car_length: f64,
+    /// positions of the doors of every car, from the front of the car
+    pub door_positions: Vec<f64>,
+    /// distance from the front of the platform to the front of the train
+    pub front_offset: f64,
+    /// distance between the "start" and "end" guides of the maps
+    pub platform_length: f64,
+    /// the passengers a car can carry
+    pub capacity: f64,
+    /// the number of trains the passengers in the link load data are spread
+    /// over. The link load data counts passengers over a whole day, so this
+    /// is the trains a day in the direction of travel
+    pub trains: f64,
+}
+
+impl Default for Formation {
+    /// a 10 car train of 20m cars with 4 doors each, filling the platform,
+    /// running 300 times a day like a busy commuter line in Tokyo
+    fn default() -> Self {
+        Self {
+            cars: 10,
+            car_length: 20.0,
+            door_positions: vec![2.5, 7.5, 12.5, 17.5],
+            front_offset: 0.0,
+            platform_length: 200.0,
+            capacity: 160.0,
+            trains: 300.0,
+        }
+    }
+}
+
+fn invalid_formation(message: String) -> crate::error::Error {
+    crate::error::Error::Formation {
+        path: None,
+        message,
+    }
+}
+
+impl Formation {
+    /// There must be at least 1 car, every number must be finite, the
+    /// lengths, capacity and number of trains must be positive and the doors
+    /// must be inside the car
+    pub fn validate(&self) -> crate::error::Result<()> {
+        if self.cars == 0 {
+            return Err(invalid_formation(
+                "the train must have at least 1 car".to_string(),
+            ));
+        }
+        let numbers = [
+            ("car_length", self.car_length),
+            ("front_offset", self.front_offset),
+            ("platform_length", self.platform_length),
+            ("capacity", self.capacity),
+            ("trains", self.trains),
+        ];
+        if let Some((name, value)) =
+            numbers.iter().find(|(_, v)| !v.is_finite())
+        {
+            return Err(invalid_formation(format!(
+                "{} must be a finite number, got {}",
+                name, value
+            )));
+        }
+        if self.car_length <= 0.0 || self.platform_length <= 0.0 {
+            return Err(invalid_formation(format!(
+                "car and platform lengths must be > 0, got {} and {}",
+                self.car_length, self.platform_length
+            )));
+        }
+        if self.capacity <= 0.0 || self.trains <= 0.0 {
+            return Err(invalid_formation(format!(
+                "capacity and trains must be > 0, got {} and {}",
+                self.capacity, self.trains
+            )));
+        }
+        if let Some(door) = self
+            .door_positions
+            .iter()
+            .find(|door| !(0.0..=self.car_length).contains(*door))
+        {
+            return Err(invalid_formation(format!(
+                "door positions must be between 0 and the car length {}, got {}",
+                self.car_length, door
+            )));
+        }
+        Ok(())
+    }
+
+    /// a distance from the front of the platform as a percentage of the
+    /// platform, the same units as the pdfs
+    pub fn to_percent(&self, metres: f64) -> f64 {
+        metres / self.platform_length * 100.0
+    }
+
+    /// the passengers that fit in a metre of a car
+    pub fn capacity_per_metre(&self) -> f64 {
+        self.capacity / self.car_length
+    }
+
+    /// every door of the train, counting cars and doors from 1 at the front
+    /// of the train
+    pub fn doors(&self) -> Vec<Door> {
+        (0..self.cars)
+            .flat_map(|car| {
+                self.door_positions.iter().enumerate().map(
+                    move |(door, pos)| Door {
+                        car: car + 1,
+                        door: door + 1,
+                        position: self.to_percent(
+                            self.front_offset
+                                + car as f64 * self.car_length
+                                + pos,
+                        ),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// the front and back of the train, as percentages of the platform
+    pub fn train_bounds(&self) -> (f64, f64) {
+        (
+            self.to_percent(self.front_offset),
+            self.to_percent(
+                self.front_offset + self.cars as f64 * self.car_length,
+            ),
+        )
+    }
+
+    /// the front and back of a car, counting from 0 at the front of the
+    /// train, as percentages of the platform
+    pub fn car_bounds(&self, car: usize) -> (f64, f64) {
+        let front = self.front_offset + car as f64 * self.car_length;
+        (
+            self.to_percent(front),
+            self.to_percent(front + self.car_length),
+        )
+    }
+}
+
+/// A row of observed car occupancy, such as from a station survey. The
+/// counts or shares of every station are normalised to shares when read
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ObservedCar {
+    /// the station the train just left
+    pub station: String,
+    /// counting from 1 at the front of the train
+    pub car: usize,
+    /// the passengers in the car, or their share of the train
+    pub share: f64,
+}
+
+/// A door of the train
+#[derive(Clone, Debug, Serialize)]
+pub struct Door {
+    pub car: usize,
+    pub door: usize,
+    /// as a percentage of the platform, the same units as the pdfs
+    pub position: f64,
+}
+
+/// A door to wait at, with the expected crowding around it
+#[derive(Clone, Debug, Serialize)]
+pub struct DoorRecommendation {
+    pub door: Door,
+    /// passengers per metre around the door
+    pub passengers_per_metre: f64,
+    /// passengers per metre as a proportion of the capacity per metre
+    pub load_factor: f64,
+}
+
+/// The expected passengers in a car after a station
+#[derive(Clone, Debug, Serialize)]
+pub struct CarLoad {
+    /// counting from 1 at the front of the train
+    pub car: usize,
+    /// proportion of the passengers in the train that are in this car
+    pub share: f64,
+    /// passengers in this car of every train
+    pub count: f64,
+    /// count as a proportion of the capacity, over 1 if the car is over
+    /// capacity
+    pub load_factor: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use statrs::distribution::{Beta, Continuous, ContinuousCDF};
+
+    /// Beta(2, 5) sampled at every 0.1% of the platform
+    fn beta_pdf() -> (Beta, Pdf) {
+        let beta = Beta::new(2.0, 5.0).unwrap();
+        let pdf = Pdf((0..=1000)
+            .map(|i| {
+                let x = i as f64 / 10.0;
+                (x, beta.pdf(x / 100.0))
+            })
+            .collect());
+        (beta, pdf)
+    }
+
+    #[test]
+    fn cdf_matches_the_beta_cdf() {
+        let (beta, pdf) = beta_pdf();
+        let cdf = pdf.cdf();
+        for (x, y) in cdf.iter().step_by(50) {
+            assert!((y - beta.cdf(x / 100.0)).abs() < 1e-4, "at {}", x);
+        }
+        assert_eq!(cdf.last().unwrap().1, 1.0);
+    }
+
+    #[test]
+    fn quantile_inverts_the_beta_cdf() {
+        let (beta, pdf) = beta_pdf();
+        let cdf = pdf.cdf();
+        for p in [0.05, 0.25, 0.5, 0.75, 0.95] {
+            let expected = beta.inverse_cdf(p) * 100.0;
+            assert!((cdf.quantile(p) - expected).abs() < 0.05, "at {}", p);
+        }
+        assert_eq!(cdf.quantile(0.0), 0.0);
+        assert_eq!(cdf.quantile(1.0), 100.0);
+    }
+
+    #[test]
+    fn mass_between_matches_the_beta_cdf() {
+        let (beta, pdf) = beta_pdf();
+        let expected = beta.cdf(0.4) - beta.cdf(0.1);
+        assert!((pdf.mass_between(10.0, 40.0) - expected).abs() < 1e-4);
+        // positions between the samples are interpolated
+        let expected = beta.cdf(0.4025) - beta.cdf(0.1025);
+        assert!((pdf.mass_between(10.25, 40.25) - expected).abs() < 1e-4);
+        // there are no passengers outside the sampled positions
+        assert!((pdf.mass_between(-50.0, 150.0) - 1.0).abs() < 1e-4);
+        assert_eq!(pdf.mass_between(40.0, 10.0), 0.0);
+    }
+
+    #[test]
+    fn stair_models_must_be_finite() {
+        let models = [
+            StairModel {
+                prop_normal_far: f64::NAN,
+                ..StairModel::default()
+            },
+            StairModel {
+                far_concentration: f64::INFINITY,
+                ..StairModel::default()
+            },
+            StairModel {
+                destination_blend: f64::NAN,
+                ..StairModel::default()
+            },
+        ];
+        for stair_model in models {
+            assert!(stair_model.validate().is_err(), "{:?}", stair_model);
+        }
+        assert!(StairModel::default().validate().is_ok());
+    }
+
+    #[test]
+    fn formations_must_be_finite() {
+        let formations = [
+            Formation {
+                car_length: f64::NAN,
+                ..Formation::default()
+            },
+            Formation {
+                capacity: f64::NAN,
+                ..Formation::default()
+            },
+            Formation {
+                trains: f64::INFINITY,
+                ..Formation::default()
+            },
+            Formation {
+                front_offset: f64::NAN,
+                ..Formation::default()
+            },
+        ];
+        for formation in formations {
+            assert!(formation.validate().is_err(), "{:?}", formation);
+        }
+        assert!(Formation::default().validate().is_ok());
+    }
+}